@@ -0,0 +1,140 @@
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use crate::field::Field;
+use crate::topology::Shape;
+
+// Time between each ring of a cascade spreading out
+const RING_TIME: Duration = Duration::from_millis(25);
+const FLAG_TIME: Duration = Duration::from_millis(150);
+const EXPLOSION_TIME: Duration = Duration::from_millis(500);
+// Time between each of the other mines being shown, less when there are lots of them
+const MINE_TIME: Duration = Duration::from_millis(60);
+const ALL_MINES_TIME: Duration = Duration::from_millis(1_500);
+// Time to look at the board after the last mine, before the end screen
+const END_PAUSE: Duration = Duration::from_millis(600);
+
+// Only changes how the board is drawn, the field is always already up to date underneath
+#[derive(Default)]
+pub struct Animations {
+    // When cells revealed by a cascade are drawn revealed, later the further they are from where it started
+    reveals: HashMap<usize, Instant>,
+    flags: HashMap<usize, Instant>,
+    explosion: Option<Explosion>,
+}
+
+struct Explosion {
+    index: usize,
+    started: Instant,
+    // The other mines, closest first, with when each is shown
+    mines: Vec<(usize, Instant)>,
+    finished: Instant,
+}
+
+impl Animations {
+    // Rings are squares around where the cascade started
+    pub fn cascade(&mut self, field: &Field, origin: usize, cells: &[usize]) {
+        let now = Instant::now();
+        for index in cells {
+            self.reveals.insert(*index, now + RING_TIME * distance(field, origin, *index));
+        }
+    }
+
+    pub fn flag(&mut self, index: usize) {
+        self.flags.insert(index, Instant::now());
+    }
+
+    pub fn explode(&mut self, field: &Field, index: usize) {
+        let started = Instant::now();
+        let mut mines: Vec<usize> = (0..field.cells.len()).filter(|mine| *mine != index && field.cells[*mine].has_mine()).collect();
+        mines.sort_by_key(|mine| distance(field, index, *mine));
+        let mine_time = MINE_TIME.min(ALL_MINES_TIME / mines.len().max(1) as u32);
+        let first_mine = started + EXPLOSION_TIME / 2;
+        let mines: Vec<(usize, Instant)> = mines.into_iter().enumerate().map(|(i, mine)| (mine, first_mine + mine_time * i as u32)).collect();
+        let finished = mines.last().map_or(first_mine, |(_, shown)| *shown).max(started + EXPLOSION_TIME) + END_PAUSE;
+        self.explosion = Some(Explosion {
+            index,
+            started,
+            mines,
+            finished,
+        });
+    }
+
+    // A mine that went off without ending the game, the other mines stay hidden
+    pub fn hit(&mut self, index: usize) {
+        let started = Instant::now();
+        self.explosion = Some(Explosion {
+            index,
+            started,
+            mines: Vec::new(),
+            finished: started + EXPLOSION_TIME,
+        });
+    }
+
+    // Jumps every animation to its end
+    pub fn skip(&mut self) {
+        *self = Animations::default();
+    }
+
+    // Drops animations that have finished
+    pub fn update(&mut self) {
+        let now = Instant::now();
+        self.reveals.retain(|_, shown| *shown > now);
+        self.flags.retain(|_, flagged| now - *flagged < FLAG_TIME);
+        if self.explosion.as_ref().is_some_and(|explosion| explosion.finished <= now) {
+            self.explosion = None;
+        }
+    }
+
+    // Held back from the end screen until the explosion is over
+    pub fn is_exploding(&self) -> bool {
+        self.explosion.is_some()
+    }
+
+    // Revealed cells the cascade hasn't reached yet
+    pub fn is_hidden(&self, index: usize) -> bool {
+        self.reveals.get(&index).is_some_and(|shown| *shown > Instant::now())
+    }
+
+    // How big a flag is drawn, from 0 to a little over 1 as it pops in
+    pub fn flag_scale(&self, index: usize) -> f32 {
+        let Some(flagged) = self.flags.get(&index) else {
+            return 1.0;
+        };
+        let progress = (flagged.elapsed().as_secs_f32() / FLAG_TIME.as_secs_f32()).min(1.0);
+        progress + 0.3 * (progress * std::f32::consts::PI).sin()
+    }
+
+    pub fn is_mine_shown(&self, index: usize) -> bool {
+        self.explosion.as_ref().is_some_and(|explosion| explosion.index == index || explosion.mines.iter().any(|(mine, shown)| *mine == index && *shown <= Instant::now()))
+    }
+
+    // The mine that went off and how far through the blast is, from 0 to 1
+    pub fn blast(&self) -> Option<(usize, f32)> {
+        let explosion = self.explosion.as_ref()?;
+        let progress = explosion.started.elapsed().as_secs_f32() / EXPLOSION_TIME.as_secs_f32();
+        (progress < 1.0).then_some((explosion.index, progress))
+    }
+}
+
+// Rings around a cell, the cells touching it are 1 away
+fn distance(field: &Field, from: usize, to: usize) -> u32 {
+    let (from_x, from_y) = field.index_to_x_y(from);
+    let (to_x, to_y) = field.index_to_x_y(to);
+    let (mut x, mut y) = (from_x.abs_diff(to_x), from_y.abs_diff(to_y));
+    match field.rules.shape {
+        // The short way round
+        Shape::Torus => {
+            x = x.min(field.width - x);
+            y = y.min(field.height - y);
+        },
+        // Steps across hexes, by way of the column each would be in if the rows weren't shifted
+        Shape::Hex => {
+            let (from_column, to_column) = (from_x as i32 - from_y as i32 / 2, to_x as i32 - to_y as i32 / 2);
+            let (column, row) = (to_column - from_column, to_y as i32 - from_y as i32);
+            return (column.abs() + row.abs() + (column + row).abs()) as u32 / 2;
+        },
+        Shape::Rectangle | Shape::Layers => {},
+    }
+    x.max(y).max(field.layer(from).abs_diff(field.layer(to))) as u32
+}
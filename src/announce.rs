@@ -0,0 +1,158 @@
+use std::process::{Child, Command};
+
+use serde::{Deserialize, Serialize};
+
+use crate::menu::Scene;
+use crate::synth::{self, semitones, Channel, Envelope, Note, Synth, Waveform};
+use crate::Game;
+
+#[derive(Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+pub enum Announcements {
+    #[default]
+    Off,
+    // Read out through speech-dispatcher
+    Speech,
+    // A distinct beep for each kind of cell, no speech needed
+    Tones,
+}
+
+pub const ANNOUNCEMENTS: [Announcements; 3] = [Announcements::Off, Announcements::Speech, Announcements::Tones];
+
+impl Announcements {
+    pub fn name(self) -> &'static str {
+        match self {
+            Announcements::Off => "Off",
+            Announcements::Speech => "Speech",
+            Announcements::Tones => "Tones",
+        }
+    }
+}
+
+// Pitch in hertz and length in milliseconds, a pitch of 0 is a rest
+type Tone = (f32, u32);
+
+// What has focus, like "row 5 column 12", and what's there, like "3".
+// Moving focus reads both, a change in the same place only reads the detail.
+#[derive(Clone, PartialEq, Default)]
+pub struct Announcement {
+    pub focus: String,
+    pub detail: String,
+    pub tones: Vec<Tone>,
+}
+
+impl Game {
+    pub fn announcement(&self) -> Announcement {
+        match self.scene {
+            Scene::Playing => {
+                let (x, y) = self.field.index_to_x_y(self.current_selection);
+                let cell = self.field.cells[self.current_selection];
+                let (detail, tones) = if cell.revealed && cell.has_mine() {
+                    ("mine".to_string(), vec![(110.0, 200)])
+                } else if cell.revealed && cell.mines_around > 0 {
+                    // Each number a step higher than the last
+                    (cell.mines_around.to_string(), vec![(semitones(440.0, f32::from(cell.mines_around - 1) * 2.0), 90)])
+                } else if cell.revealed {
+                    ("empty".to_string(), vec![(330.0, 40)])
+                } else if cell.flags > 1 {
+                    (format!("{} flags, {} flags left", cell.flags, self.field.flags_left), vec![(660.0, 60), (0.0, 30), (660.0, 60)])
+                } else if cell.flagged() {
+                    (format!("flagged, {} flags left", self.field.flags_left), vec![(660.0, 60), (0.0, 30), (660.0, 60)])
+                } else if cell.questioned {
+                    ("question mark".to_string(), vec![(330.0, 60), (440.0, 60)])
+                } else {
+                    ("hidden".to_string(), vec![(220.0, 60)])
+                };
+                let layer = if self.field.rules.layers > 1 { format!(" layer {}", self.field.layer(self.current_selection) + 1) } else { String::new() };
+                Announcement {
+                    focus: format!("row {} column {}{}", y + 1, x + 1, layer),
+                    detail,
+                    tones,
+                }
+            },
+            Scene::End => {
+                let seconds = self.game_duration.map_or(0, |game_duration| game_duration.as_secs());
+                Announcement {
+                    focus: if self.was_winner { "You won".to_string() } else { "You lost".to_string() },
+                    detail: format!("{} seconds, press any button to play again", seconds),
+                    tones: if self.was_winner {
+                        vec![(523.0, 120), (659.0, 120), (784.0, 240)]
+                    } else {
+                        vec![(392.0, 160), (311.0, 160), (196.0, 320)]
+                    },
+                }
+            },
+            scene => Announcement {
+                // The arrows around values are only there to look at
+                focus: format!("{}, {}", scene.title(), self.menu_rows().get(self.menu_selection).map_or("", |(_, row)| row.as_str())).replace(['<', '>'], ""),
+                detail: String::new(),
+                tones: vec![(880.0, 30)],
+            },
+        }
+    }
+}
+
+pub struct Announcer {
+    last: Announcement,
+    last_mode: Announcements,
+    speech: Option<Child>,
+    // Turns false once spd-say turns out to be missing, tones are used instead
+    speech_available: bool,
+}
+
+impl Announcer {
+    pub fn new() -> Announcer {
+        Announcer {
+            last: Announcement::default(),
+            last_mode: Announcements::Off,
+            speech: None,
+            speech_available: true,
+        }
+    }
+
+    // Called every frame, only says something when what's focused, or what's there, changed
+    pub fn announce(&mut self, mode: Announcements, announcement: Announcement, synth: &mut Synth) {
+        if mode != self.last_mode {
+            self.last_mode = mode;
+            self.last = Announcement::default();
+        }
+        if mode == Announcements::Off || announcement == self.last {
+            return;
+        }
+        let text = if announcement.focus != self.last.focus {
+            format!("{}, {}", announcement.focus, announcement.detail)
+        } else {
+            announcement.detail.clone()
+        };
+        if mode == Announcements::Speech && self.speech_available {
+            self.speak(text.trim_end_matches(", "));
+        } else {
+            play(&announcement.tones, synth);
+        }
+        self.last = announcement;
+    }
+
+    fn speak(&mut self, text: &str) {
+        // Reap the last one so they don't pile up as zombies
+        if let Some(mut speech) = self.speech.take() {
+            let _ = speech.try_wait();
+        }
+        // Text priority cuts off whatever was being said before
+        match Command::new("spd-say").args(["--priority", "text", text]).spawn() {
+            Ok(speech) => self.speech = Some(speech),
+            Err(e) => {
+                eprintln!("Couldn't run spd-say, using tones instead: {}", e);
+                self.speech_available = false;
+            },
+        }
+    }
+}
+
+// Soft triangle notes, so they stand apart from the square wave sound effects
+fn play(tones: &[Tone], synth: &mut Synth) {
+    let channel = Channel {
+        waveform: Waveform::Triangle,
+        envelope: Envelope::FLAT,
+        notes: tones.iter().map(|(pitch, length)| Note::new(*pitch, *length as f32 / 1_000.0)).collect(),
+    };
+    synth.play(&synth::render(&[channel]), 1.0);
+}
@@ -0,0 +1,116 @@
+use std::time::{Duration, Instant};
+
+// Game timer that can be paused, the time spent paused is never counted
+#[derive(Clone, Copy, Default)]
+pub struct Clock {
+    started: bool,
+    accumulated: Duration,
+    running_since: Option<Instant>,
+}
+
+impl Clock {
+    // Starts the clock on the first move, later calls do nothing
+    pub fn start(&mut self) {
+        self.start_at(Instant::now());
+    }
+
+    pub fn pause(&mut self) {
+        self.pause_at(Instant::now());
+    }
+
+    pub fn resume(&mut self) {
+        self.resume_at(Instant::now());
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        self.elapsed_at(Instant::now())
+    }
+
+    // Counted as if it had been played
+    pub fn add_penalty(&mut self, penalty: Duration) {
+        self.accumulated += penalty;
+    }
+
+    pub fn is_started(&self) -> bool {
+        self.started
+    }
+
+    // The same as the ones above, with the time passed in so tests can pick it
+    fn start_at(&mut self, now: Instant) {
+        if !self.started {
+            self.started = true;
+            self.running_since = Some(now);
+        }
+    }
+
+    fn pause_at(&mut self, now: Instant) {
+        if let Some(running_since) = self.running_since.take() {
+            self.accumulated += now.saturating_duration_since(running_since);
+        }
+    }
+
+    fn resume_at(&mut self, now: Instant) {
+        if self.started && self.running_since.is_none() {
+            self.running_since = Some(now);
+        }
+    }
+
+    fn elapsed_at(&self, now: Instant) -> Duration {
+        self.accumulated + self.running_since.map_or(Duration::ZERO, |running_since| now.saturating_duration_since(running_since))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn seconds(seconds: u64) -> Duration {
+        Duration::from_secs(seconds)
+    }
+
+    #[test]
+    fn nothing_counted_before_starting() {
+        let (mut clock, start) = (Clock::default(), Instant::now());
+        assert_eq!(clock.elapsed_at(start + seconds(5)), Duration::ZERO);
+        // Resuming a clock that never started doesn't start it
+        clock.resume_at(start);
+        assert!(!clock.is_started());
+        assert_eq!(clock.elapsed_at(start + seconds(5)), Duration::ZERO);
+    }
+
+    #[test]
+    fn starting_again_does_nothing() {
+        let (mut clock, start) = (Clock::default(), Instant::now());
+        clock.start_at(start);
+        clock.start_at(start + seconds(3));
+        assert_eq!(clock.elapsed_at(start + seconds(4)), seconds(4));
+    }
+
+    #[test]
+    fn paused_time_is_not_counted() {
+        let (mut clock, start) = (Clock::default(), Instant::now());
+        clock.start_at(start);
+        clock.pause_at(start + seconds(10));
+        assert_eq!(clock.elapsed_at(start + seconds(60)), seconds(10));
+        // Pausing twice doesn't count the time between
+        clock.pause_at(start + seconds(30));
+        clock.resume_at(start + seconds(60));
+        assert_eq!(clock.elapsed_at(start + seconds(65)), seconds(15));
+        clock.resume_at(start + seconds(70));
+        assert_eq!(clock.elapsed_at(start + seconds(70)), seconds(20));
+    }
+
+    #[test]
+    fn penalties_stack() {
+        let (mut clock, start) = (Clock::default(), Instant::now());
+        clock.start_at(start);
+        clock.add_penalty(seconds(30));
+        clock.add_penalty(seconds(30));
+        assert_eq!(clock.elapsed_at(start + seconds(1)), seconds(61));
+        // Penalties while paused still count, and the pause still doesn't
+        clock.pause_at(start + seconds(2));
+        clock.add_penalty(seconds(30));
+        clock.resume_at(start + seconds(50));
+        assert_eq!(clock.elapsed_at(start + seconds(53)), seconds(95));
+    }
+}
@@ -0,0 +1,176 @@
+use std::fs;
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+
+use crate::announce::Announcements;
+use crate::input::{self, Bindings};
+use crate::music::Repeat;
+use crate::palette::Palette;
+use crate::theme::DEFAULT_THEME;
+use crate::topology::{Neighbourhood, Shape, DEFAULT_LAYERS};
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    // Name of one of the DIFFICULTIES
+    pub board: String,
+    pub custom_width: usize,
+    pub custom_height: usize,
+    pub custom_mines: u32,
+    pub shape: Shape,
+    // Only used by 3D boards
+    pub layers: usize,
+    pub neighbourhood: Neighbourhood,
+    pub mines_per_cell: u8,
+    // Mines that can be hit before the game is lost, 0 loses on the first one
+    pub lives: u32,
+    pub music: bool,
+    // Percent
+    pub music_volume: u8,
+    pub music_shuffle: bool,
+    pub music_repeat: Repeat,
+    pub sound_effects: bool,
+    // Percent, separate from the music
+    pub sound_volume: u8,
+    // Silences music and sound effects without changing either setting
+    pub muted: bool,
+    // Name of a directory in themes/, or the embedded theme
+    pub theme: String,
+    pub palette: Palette,
+    pub announcements: Announcements,
+    // Cascades spreading out, flags popping in and mines going off one by one
+    pub animations: bool,
+    // Size of the window compared to the game, the window can still be resized freely
+    pub window_scale: u32,
+    // Only scale the game by whole numbers, leaving a bigger border around it
+    pub integer_scaling: bool,
+    pub fullscreen: bool,
+    pub question_marks: bool,
+    pub first_click_safe: bool,
+    // Milliseconds a move is held before it repeats
+    pub repeat_delay: u32,
+    // Repeats per second, before speeding up
+    pub repeat_rate: u32,
+    #[serde(deserialize_with = "input::deserialize_keyboard")]
+    pub key_bindings: Bindings,
+    #[serde(deserialize_with = "input::deserialize_controller")]
+    pub button_bindings: Bindings,
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            board: "Classic".to_string(),
+            custom_width: 24,
+            custom_height: 24,
+            custom_mines: 99,
+            shape: Shape::Rectangle,
+            layers: DEFAULT_LAYERS,
+            neighbourhood: Neighbourhood::Standard,
+            mines_per_cell: 1,
+            lives: 0,
+            music: true,
+            music_volume: 100,
+            music_shuffle: false,
+            music_repeat: Repeat::All,
+            sound_effects: true,
+            sound_volume: 100,
+            muted: false,
+            theme: DEFAULT_THEME.to_string(),
+            palette: Palette::Standard,
+            announcements: Announcements::Off,
+            animations: true,
+            window_scale: 1,
+            integer_scaling: false,
+            fullscreen: false,
+            question_marks: false,
+            first_click_safe: true,
+            repeat_delay: 250,
+            repeat_rate: 15,
+            key_bindings: Bindings::keyboard(),
+            button_bindings: Bindings::controller(),
+        }
+    }
+}
+
+impl Config {
+    pub fn directory() -> Option<PathBuf> {
+        let config_home = match std::env::var_os("XDG_CONFIG_HOME").filter(|path| !path.is_empty()) {
+            Some(path) => PathBuf::from(path),
+            None => PathBuf::from(std::env::var_os("HOME")?).join(".config"),
+        };
+        Some(config_home.join("minesweeper"))
+    }
+
+    pub fn path() -> Option<PathBuf> {
+        Some(Config::directory()?.join("config.toml"))
+    }
+
+    // Missing or broken config files fall back to the defaults
+    pub fn load() -> Config {
+        let Some(path) = Config::path() else {
+            return Config::default();
+        };
+        let Ok(contents) = fs::read_to_string(&path) else {
+            return Config::default();
+        };
+        match toml::from_str(&contents) {
+            Ok(config) => config,
+            Err(e) => {
+                eprintln!("Couldn't parse {}: {}", path.display(), e);
+                Config::default()
+            },
+        }
+    }
+
+    pub fn save(&self) {
+        let Some(path) = Config::path() else {
+            return;
+        };
+        let contents = match toml::to_string_pretty(self) {
+            Ok(contents) => contents,
+            Err(e) => {
+                eprintln!("Couldn't serialize config: {}", e);
+                return;
+            },
+        };
+        if let Some(parent) = path.parent() {
+            let _ = fs::create_dir_all(parent);
+        }
+        if let Err(e) = fs::write(&path, contents) {
+            eprintln!("Couldn't write {}: {}", path.display(), e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::input::{Action, ACTIONS};
+
+    #[test]
+    fn config_round_trips() {
+        let config: Config = toml::from_str(&toml::to_string_pretty(&Config::default()).unwrap()).unwrap();
+        for action in ACTIONS {
+            assert_eq!(config.key_bindings.get(action), Bindings::keyboard().get(action));
+            assert_eq!(config.button_bindings.get(action), Bindings::controller().get(action));
+        }
+    }
+
+    // A table that only rebinds one action used to unbind every other one
+    #[test]
+    fn partial_bindings_keep_the_defaults() {
+        let config: Config = toml::from_str("[key_bindings]\nreveal = [\"Z\"]\n\n[button_bindings]\npause = []\n").unwrap();
+        let config: Config = toml::from_str(&toml::to_string_pretty(&config).unwrap()).unwrap();
+        for action in ACTIONS {
+            let (keys, buttons) = match action {
+                Action::Reveal => (vec!["Z".to_string()], Bindings::controller().get(action).clone()),
+                Action::Pause => (Bindings::keyboard().get(action).clone(), Vec::new()),
+                _ => (Bindings::keyboard().get(action).clone(), Bindings::controller().get(action).clone()),
+            };
+            assert_eq!(config.key_bindings.get(action), &keys);
+            assert_eq!(config.button_bindings.get(action), &buttons);
+        }
+    }
+}
@@ -0,0 +1,392 @@
+use std::collections::HashMap;
+
+use crate::field::{Cell, Field, Rules, MAX_MINES_PER_CELL};
+use crate::sound::Sound;
+use crate::topology::Shape;
+use crate::{Game, CASCADE_SIZE, LIFE_PENALTY};
+
+// Cells along each side of a chunk
+const CHUNK_SIZE: i32 = 16;
+// Low enough that empty regions always come to an end
+const MINE_CHANCE: f64 = 0.18;
+// The board that's drawn is a window this big onto the world
+pub const VIEW_COLUMNS: usize = 30;
+pub const VIEW_ROWS: usize = 16;
+// Cells kept between the cursor and the edge of the view, the camera moves instead
+const SCROLL_MARGIN: i32 = 4;
+
+// A board with no edges, made a chunk at a time from the seed as it's explored
+pub struct Endless {
+    seed: u64,
+    // Always flat, only the neighbourhood and mines per cell change anything
+    rules: Rules,
+    chunks: HashMap<(i32, i32), Vec<Cell>>,
+    // World position of the top left cell in view
+    camera: (i32, i32),
+    // Safe cells revealed, the score
+    pub cleared: u32,
+}
+
+impl Endless {
+    // The cell at 0, 0 starts in the middle of the view
+    pub fn new(seed: u64, rules: Rules) -> Endless {
+        Endless {
+            seed,
+            rules: Rules { shape: Shape::Rectangle, layers: 1, neighbourhood: rules.neighbourhood, mines_per_cell: rules.mines_per_cell.clamp(1, MAX_MINES_PER_CELL) },
+            chunks: HashMap::new(),
+            camera: (-(VIEW_COLUMNS as i32) / 2, -(VIEW_ROWS as i32) / 2),
+            cleared: 0,
+        }
+    }
+
+    fn cell_mut(&mut self, x: i32, y: i32) -> &mut Cell {
+        let (chunk_x, chunk_y) = (x.div_euclid(CHUNK_SIZE), y.div_euclid(CHUNK_SIZE));
+        let (seed, rules) = (self.seed, self.rules);
+        let cells = self.chunks.entry((chunk_x, chunk_y)).or_insert_with(|| generate(seed, rules, chunk_x, chunk_y));
+        &mut cells[(y.rem_euclid(CHUNK_SIZE) * CHUNK_SIZE + x.rem_euclid(CHUNK_SIZE)) as usize]
+    }
+
+    // Counted when the cell is revealed, making the chunks next to it first if need be, so numbers on chunk borders count both sides
+    fn mines_around(&mut self, x: i32, y: i32) -> u8 {
+        self.rules.neighbourhood.around(x, y).map(|(x, y)| self.cell_mut(x, y).mines).sum()
+    }
+
+    fn world_position(&self, field: &Field, index: usize) -> (i32, i32) {
+        let (x, y) = field.index_to_x_y(index);
+        (self.camera.0 + x as i32, self.camera.1 + y as i32)
+    }
+
+    // Where a world position is on the board, if it's in view
+    fn view_index(&self, field: &Field, (x, y): (i32, i32)) -> Option<usize> {
+        let (x, y) = (x - self.camera.0, y - self.camera.1);
+        ((0..field.width as i32).contains(&x) && (0..field.height as i32).contains(&y)).then(|| field.x_y_to_index(x as usize, y as usize))
+    }
+
+    // Returns the cells revealed in the order they were revealed, or None if the cell had a mine
+    fn reveal(&mut self, x: i32, y: i32) -> Option<Vec<(i32, i32)>> {
+        let cell = self.cell_mut(x, y);
+        if cell.revealed {
+            return Some(Vec::new());
+        }
+        let mut hit_mine = false;
+        cell.reveal(|_| {}, || hit_mine = true);
+        if hit_mine {
+            return None;
+        }
+        let mut revealed = Vec::<(i32, i32)>::new();
+        let mut to_visit = vec![(x, y)];
+        while let Some((x, y)) = to_visit.pop() {
+            let mines_around = self.mines_around(x, y);
+            self.cell_mut(x, y).mines_around = mines_around;
+            revealed.push((x, y));
+            if mines_around > 0 {
+                continue;
+            }
+            for (x, y) in self.rules.neighbourhood.around(x, y) {
+                let neighbour = self.cell_mut(x, y);
+                if neighbour.should_reveal() {
+                    neighbour.reveal(|_| {}, || {});
+                    to_visit.push((x, y));
+                }
+            }
+        }
+        self.cleared += revealed.len() as u32;
+        Some(revealed)
+    }
+
+    // There's no running out of flags with no mine count, the cell always has room for as many as it can take
+    fn flag(&mut self, x: i32, y: i32, question_marks: bool) -> Option<bool> {
+        let (mines_per_cell, mut flags_left) = (self.rules.mines_per_cell, u32::from(self.rules.mines_per_cell));
+        self.cell_mut(x, y).cycle_flag(mines_per_cell, &mut flags_left, question_marks)
+    }
+
+    // The hidden neighbours to reveal, if the number has as many flags around it
+    fn chord(&mut self, x: i32, y: i32) -> Vec<(i32, i32)> {
+        let cell = *self.cell_mut(x, y);
+        if !cell.revealed || cell.mines_around == 0 {
+            return Vec::new();
+        }
+        let neighbours: Vec<(i32, i32)> = self.rules.neighbourhood.around(x, y).collect();
+        // Mines that went off without ending the game count as flagged
+        let flags_around: u8 = neighbours.iter().map(|(x, y)| { let neighbour = self.cell_mut(*x, *y); if neighbour.revealed { neighbour.mines } else { neighbour.flags } }).sum();
+        if flags_around != cell.mines_around {
+            return Vec::new();
+        }
+        neighbours.into_iter().filter(|(x, y)| { let neighbour = self.cell_mut(*x, *y); !neighbour.revealed && !neighbour.flagged() }).collect()
+    }
+}
+
+// The same seed always makes the same chunk, whatever order the chunks are visited in
+fn generate(seed: u64, rules: Rules, chunk_x: i32, chunk_y: i32) -> Vec<Cell> {
+    (0..CHUNK_SIZE * CHUNK_SIZE)
+        .map(|i| {
+            let mut cell = Cell::new();
+            cell.mines = mines_at(seed, rules, chunk_x * CHUNK_SIZE + i % CHUNK_SIZE, chunk_y * CHUNK_SIZE + i / CHUNK_SIZE);
+            cell
+        })
+        .collect()
+}
+
+// Each cell's mines come from hashing the seed with where the cell is, rather than from rand, so a seed makes the same board whatever version of rand is used
+fn mines_at(seed: u64, rules: Rules, x: i32, y: i32) -> u8 {
+    // Nothing on or around where the game starts, so the first reveal always opens up
+    if (x, y) == (0, 0) || rules.neighbourhood.around(0, 0).any(|position| position == (x, y)) {
+        return 0;
+    }
+    let position = (u64::from(x as u32) << 32) | u64::from(y as u32);
+    let hash = mix(seed ^ mix(position));
+    if hash as f64 >= MINE_CHANCE * u64::MAX as f64 {
+        return 0;
+    }
+    1 + (hash % u64::from(rules.mines_per_cell)) as u8
+}
+
+// SplitMix64's mixing step
+fn mix(value: u64) -> u64 {
+    let value = value.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    let value = (value ^ (value >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    let value = (value ^ (value >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    value ^ (value >> 31)
+}
+
+impl Game {
+    pub fn endless_seed(&self) -> u64 {
+        self.endless_seed.unwrap_or_else(rand::random)
+    }
+
+    pub fn start_endless(&mut self, seed: u64, rules: Rules) {
+        let endless = Endless::new(seed, rules);
+        // The view draws flags and presses chords by the same rules as the world
+        let mut field = Field::empty(VIEW_COLUMNS, VIEW_ROWS, 0);
+        field.rules = endless.rules;
+        self.reset(field);
+        self.endless = Some(endless);
+        self.current_selection = self.field.x_y_to_index(VIEW_COLUMNS / 2, VIEW_ROWS / 2);
+        self.show_endless();
+    }
+
+    // Copies the part of the world in view onto the field, which is what gets drawn and clicked on
+    fn show_endless(&mut self) {
+        let Some(endless) = &mut self.endless else {
+            return;
+        };
+        for index in 0..self.field.cells.len() {
+            let (x, y) = endless.world_position(&self.field, index);
+            self.field.cells[index] = *endless.cell_mut(x, y);
+        }
+    }
+
+    // The cursor moves freely in the middle of the view, near the edges the camera moves with it
+    pub fn move_endless_cursor(&mut self, x: i32, y: i32) {
+        // Moving the camera would cut the explosion short
+        if self.should_die {
+            return;
+        }
+        let (current_x, current_y) = self.field.index_to_x_y(self.current_selection);
+        let (new_x, new_y) = (current_x as i32 + x, current_y as i32 + y);
+        let (scroll_x, scroll_y) = (scroll(new_x, x, self.field.width as i32), scroll(new_y, y, self.field.height as i32));
+        self.move_camera(scroll_x, scroll_y);
+        self.current_selection = self.field.x_y_to_index((new_x - scroll_x) as usize, (new_y - scroll_y) as usize);
+    }
+
+    // The mouse can't go past the edge of the window, so moving it onto a cell near the edge moves the camera a cell that way instead.
+    // The cursor stays under the mouse, returns whether the camera moved
+    pub fn hover_endless(&mut self, index: usize) -> bool {
+        if self.endless.is_none() || self.should_die || index == self.current_selection {
+            return false;
+        }
+        let ((x, y), (current_x, current_y)) = (self.field.index_to_x_y(index), self.field.index_to_x_y(self.current_selection));
+        let (step_x, step_y) = ((x as i32 - current_x as i32).signum(), (y as i32 - current_y as i32).signum());
+        let moved = self.move_camera(scroll(x as i32, step_x, self.field.width as i32), scroll(y as i32, step_y, self.field.height as i32));
+        self.current_selection = index;
+        moved
+    }
+
+    // Animations are kept by where cells are in view, so they're finished early rather than played on the wrong cells once the camera moves
+    fn move_camera(&mut self, x: i32, y: i32) -> bool {
+        let Some(endless) = &mut self.endless else {
+            return false;
+        };
+        if (x, y) == (0, 0) {
+            return false;
+        }
+        endless.camera = (endless.camera.0 + x, endless.camera.1 + y);
+        self.animations.skip();
+        self.show_endless();
+        true
+    }
+
+    pub fn reveal_endless(&mut self, index: usize) {
+        let Some(endless) = &self.endless else {
+            return;
+        };
+        let position = endless.world_position(&self.field, index);
+        self.reveal_world(position);
+        self.show_endless();
+    }
+
+    fn reveal_world(&mut self, position: (i32, i32)) {
+        let Some(endless) = &mut self.endless else {
+            return;
+        };
+        self.clock.start();
+        let index = endless.view_index(&self.field, position);
+        match endless.reveal(position.0, position.1) {
+            Some(revealed) if revealed.is_empty() => {},
+            Some(revealed) => {
+                if self.config.animations {
+                    if let Some(index) = index {
+                        let cells: Vec<usize> = revealed[1..].iter().filter_map(|position| endless.view_index(&self.field, *position)).collect();
+                        self.animations.cascade(&self.field, index, &cells);
+                    }
+                }
+                let mines_around = endless.cell_mut(position.0, position.1).mines_around;
+                self.sounds.push(if revealed.len() >= CASCADE_SIZE { Sound::Cascade } else { Sound::Reveal(mines_around) });
+            },
+            // Lives work the same as on other boards, without any mine count to take the mine off
+            None if self.lives > 0 => {
+                self.lives -= 1;
+                self.lives_used += 1;
+                self.clock.add_penalty(LIFE_PENALTY);
+                if let Some(index) = index.filter(|_| self.config.animations) {
+                    self.animations.hit(index);
+                }
+                self.sounds.push(Sound::Explosion);
+            },
+            None => {
+                self.should_die = true;
+                self.hit_mines.extend(index);
+                self.show_endless();
+                if let Some(index) = index.filter(|_| self.config.animations) {
+                    self.animations.explode(&self.field, index);
+                }
+                self.sounds.push(Sound::Explosion);
+            },
+        }
+    }
+
+    pub fn flag_endless(&mut self, index: usize) {
+        let Some(endless) = &mut self.endless else {
+            return;
+        };
+        self.clock.start();
+        let (x, y) = endless.world_position(&self.field, index);
+        match endless.flag(x, y, self.config.question_marks) {
+            Some(true) => {
+                if self.config.animations {
+                    self.animations.flag(index);
+                }
+                self.sounds.push(Sound::Flag);
+            },
+            Some(false) => self.sounds.push(Sound::Unflag),
+            None => {},
+        }
+        self.show_endless();
+    }
+
+    pub fn chord_endless(&mut self, index: usize) {
+        let Some(endless) = &mut self.endless else {
+            return;
+        };
+        let (x, y) = endless.world_position(&self.field, index);
+        for position in endless.chord(x, y) {
+            self.reveal_world(position);
+        }
+        self.show_endless();
+    }
+}
+
+// How far the camera moves so a step doesn't take the cursor into the margin, never more than the step itself
+fn scroll(position: i32, step: i32, size: i32) -> i32 {
+    if step < 0 {
+        (position - SCROLL_MARGIN).clamp(step, 0)
+    } else {
+        (position - (size - 1 - SCROLL_MARGIN)).clamp(0, step)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::topology::NEIGHBOURHOODS;
+
+    #[test]
+    fn seeds_make_the_same_chunks() {
+        for (chunk_x, chunk_y) in [(0, 0), (-1, 0), (3, -7), (-20, 11)] {
+            let mines = |seed| generate(seed, Rules::default(), chunk_x, chunk_y).iter().map(|cell| cell.mines).collect::<Vec<u8>>();
+            assert_eq!(mines(42), mines(42));
+            assert_ne!(mines(42), mines(43));
+        }
+    }
+
+    #[test]
+    fn about_the_right_number_of_mines() {
+        let cells: Vec<Cell> = (-4..4).flat_map(|chunk| generate(7, Rules::default(), chunk, chunk * 3)).collect();
+        let mines = cells.iter().filter(|cell| cell.has_mine()).count() as f64 / cells.len() as f64;
+        assert!((mines - MINE_CHANCE).abs() < 0.03, "{}", mines);
+    }
+
+    // Every neighbourhood with one mine a cell, and the standard one with up to three
+    fn all_rules() -> Vec<Rules> {
+        let mut rules: Vec<Rules> = NEIGHBOURHOODS.into_iter().map(|neighbourhood| Rules { neighbourhood, ..Rules::default() }).collect();
+        rules.push(Rules { mines_per_cell: MAX_MINES_PER_CELL, ..Rules::default() });
+        rules
+    }
+
+    #[test]
+    fn start_is_clear() {
+        for rules in all_rules() {
+            let mut endless = Endless::new(1, rules);
+            assert!(!endless.cell_mut(0, 0).has_mine());
+            assert_eq!(endless.mines_around(0, 0), 0);
+            assert!(endless.reveal(0, 0).is_some_and(|revealed| revealed.len() > 1));
+        }
+    }
+
+    // A number on the edge of a chunk counts the mines in the chunks next to it, however the chunks came to be made
+    #[test]
+    fn counts_cross_chunk_borders() {
+        for rules in all_rules() {
+            let mut endless = Endless::new(99, rules);
+            for (x, y) in [(CHUNK_SIZE - 1, 5), (CHUNK_SIZE, 5), (-1, -1 - CHUNK_SIZE), (3 * CHUNK_SIZE - 1, 2 * CHUNK_SIZE - 1)] {
+                let expected: u8 = rules.neighbourhood.around(x, y).map(|(x, y)| mines_at(99, rules, x, y)).sum();
+                assert_eq!(endless.mines_around(x, y), expected);
+            }
+        }
+    }
+
+    #[test]
+    fn cells_hold_up_to_mines_per_cell() {
+        let rules = Rules { mines_per_cell: MAX_MINES_PER_CELL, ..Rules::default() };
+        let cells = generate(3, rules, 2, 2);
+        for mines in 1..=MAX_MINES_PER_CELL {
+            assert!(cells.iter().any(|cell| cell.mines == mines));
+        }
+        assert!(cells.iter().all(|cell| cell.mines <= MAX_MINES_PER_CELL));
+    }
+
+    #[test]
+    fn flags_stack_without_running_out() {
+        let mut endless = Endless::new(4, Rules { mines_per_cell: 2, ..Rules::default() });
+        assert_eq!(endless.flag(5, 5, false), Some(true));
+        assert_eq!(endless.flag(5, 5, false), Some(true));
+        assert_eq!(endless.cell_mut(5, 5).flags, 2);
+        assert_eq!(endless.flag(5, 5, false), Some(false));
+        assert_eq!(endless.cell_mut(5, 5).flags, 0);
+    }
+
+    #[test]
+    fn hovering_near_the_edge_scrolls() {
+        let mut game = Game::new(Field::empty(1, 1, 0));
+        game.start_endless(5, Rules::default());
+        let camera = game.endless.as_ref().unwrap().camera;
+        let edge = game.field.x_y_to_index(VIEW_COLUMNS - 1, VIEW_ROWS / 2);
+        assert!(game.hover_endless(edge));
+        assert_eq!(game.endless.as_ref().unwrap().camera, (camera.0 + 1, camera.1));
+        assert_eq!(game.current_selection, edge);
+        // Staying on the cell, or moving back towards the middle, leaves the camera where it is
+        assert!(!game.hover_endless(edge));
+        assert!(!game.hover_endless(edge - 1));
+        assert!(!game.hover_endless(game.field.x_y_to_index(VIEW_COLUMNS / 2, VIEW_ROWS / 2)));
+    }
+}
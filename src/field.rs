@@ -0,0 +1,529 @@
+use std::time::{Duration, Instant};
+
+use rand::seq::SliceRandom;
+
+use crate::topology::{Hex, Layered, Neighbourhood, Rectangle, Shape, Topology, Torus};
+
+pub const MAX_MINES_PER_CELL: u8 = 3;
+
+#[derive(Clone, Copy)]
+pub struct Cell {
+    pub revealed: bool,
+    // How many mines the player has marked here, only ever more than one when cells can hold more than one mine
+    pub flags: u8,
+    // Only used when question marks are turned on, cycled to after a flag
+    pub questioned: bool,
+    pub mines: u8,
+    // Every mine in the cells around, not how many of them have mines
+    pub mines_around: u8,
+}
+
+impl Cell {
+    pub fn new() -> Cell {
+        Cell {
+            revealed: false,
+            flags: 0,
+            questioned: false,
+            mines: 0,
+            mines_around: 0,
+        }
+    }
+
+    pub fn has_mine(&self) -> bool {
+        self.mines > 0
+    }
+
+    pub fn flagged(&self) -> bool {
+        self.flags > 0
+    }
+
+    // Cells that can hold more than one mine take another flag each time until they're full or the flags run out, then the next press takes them all off.
+    // Returns whether a flag went on or came off, None if nothing changed
+    pub fn cycle_flag(&mut self, mines_per_cell: u8, flags_left: &mut u32, question_marks: bool) -> Option<bool> {
+        if self.revealed {
+            None
+        } else if self.flagged() && (self.flags >= mines_per_cell || *flags_left == 0) {
+            *flags_left += u32::from(self.flags);
+            self.flags = 0;
+            self.questioned = question_marks;
+            Some(false)
+        } else if self.questioned {
+            self.questioned = false;
+            Some(false)
+        } else if *flags_left > 0 {
+            *flags_left -= 1;
+            self.flags += 1;
+            Some(true)
+        } else {
+            None
+        }
+    }
+
+    pub fn should_reveal(&self) -> bool {
+        !self.has_mine() && !self.revealed
+    }
+
+    // flagged is given how many flags came off the cell
+    pub fn reveal<F, G>(&mut self, flagged: F, revealed_mine: G)
+    where
+        F: FnOnce(u8),
+        G: FnOnce()
+    {
+        self.revealed = true;
+        self.questioned = false;
+        if self.flagged() {
+            flagged(self.flags);
+            self.flags = 0;
+        }
+        if self.has_mine() {
+            revealed_mine();
+        }
+    }
+}
+
+// Everything that changes how a board plays, besides its size and mines
+#[derive(Clone, Copy, PartialEq)]
+pub struct Rules {
+    pub shape: Shape,
+    // More than one only on 3D boards, each layer is a whole width by height board
+    pub layers: usize,
+    pub neighbourhood: Neighbourhood,
+    // Most mines one cell can hold, the board's mine count is the total of every cell's mines
+    pub mines_per_cell: u8,
+}
+
+impl Default for Rules {
+    fn default() -> Rules {
+        Rules {
+            shape: Shape::Rectangle,
+            layers: 1,
+            neighbourhood: Neighbourhood::Standard,
+            mines_per_cell: 1,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct Field {
+    pub width: usize,
+    pub height: usize,
+    pub mines: u32,
+    pub cells: Vec<Cell>,
+    pub flags_left: u32,
+    pub rules: Rules,
+}
+
+impl Field {
+    pub fn new(width: usize, height: usize, mines: u32, rules: Rules) -> Field {
+        let mut field = Field::empty(width, height, mines);
+        // Layers are only used by 3D boards
+        field.rules = Rules { layers: if rules.shape == Shape::Layers { rules.layers.max(1) } else { 1 }, mines_per_cell: rules.mines_per_cell.clamp(1, MAX_MINES_PER_CELL), ..rules };
+        field.cells = vec![Cell::new(); width * height * field.rules.layers];
+        // Every mine each cell could hold, in a random order
+        let mut slots: Vec<usize> = (0..field.cells.len()).flat_map(|i| std::iter::repeat_n(i, field.rules.mines_per_cell as usize)).collect();
+        slots.shuffle(&mut rand::thread_rng());
+        let mut skipped = Vec::<usize>::new();
+        let mut mines_to_place = mines;
+
+        // Place Mines, none straight next to another while there's room
+        for i in slots {
+            if mines_to_place == 0 {
+                break;
+            }
+            // Another mine in the same cell doesn't put it next to anything new
+            if !field.cells[i].has_mine() && field.next_to_mine(i) {
+                skipped.push(i);
+                continue;
+            }
+            field.cells[i].mines += 1;
+            mines_to_place -= 1;
+        }
+        // Dense boards run out of room to spread the mines out, the rest go wherever's left
+        for i in skipped.into_iter().take(mines_to_place as usize) {
+            field.cells[i].mines += 1;
+            mines_to_place -= 1;
+        }
+        // Only left over when there were more mines than the cells could hold
+        field.mines -= mines_to_place;
+        field.flags_left = field.mines;
+
+        field.count_mines_around();
+
+        field
+    }
+
+    // Going by index rather than the topology, mines are only spread out to make boards nicer to play
+    fn next_to_mine(&self, index: usize) -> bool {
+        [index.checked_sub(1), index.checked_sub(self.width), Some(index + 1), Some(index + self.width)]
+            .into_iter()
+            .flatten()
+            .any(|neighbour| self.cells.get(neighbour).is_some_and(Cell::has_mine))
+    }
+
+    fn count_mines_around(&mut self) {
+        let topology = self.topology();
+        let mut neighbours = Vec::<usize>::new();
+        for cell_index in 0..self.cells.len() {
+            if self.cells[cell_index].has_mine() {
+                continue;
+            }
+            neighbours.clear();
+            topology.neighbours(cell_index, &mut neighbours);
+            self.cells[cell_index].mines_around = neighbours.iter().map(|neighbour| self.cells[*neighbour].mines).sum();
+        }
+    }
+
+    // First click safety, moves the cell's mines off it so the first reveal never loses
+    pub fn move_mine_from(&mut self, index: usize) {
+        if !self.cells[index].has_mine() {
+            return;
+        }
+        let empty_cells: Vec<usize> = (0..self.cells.len()).filter(|i| *i != index && !self.cells[*i].has_mine()).collect();
+        if empty_cells.is_empty() {
+            return;
+        }
+        let new_index = empty_cells[rand::random::<usize>() % empty_cells.len()];
+        self.cells[new_index].mines = self.cells[index].mines;
+        self.cells[index].mines = 0;
+        self.count_mines_around();
+    }
+
+    // A field with no mines placed, used by network clients to mirror the server's field
+    pub fn empty(width: usize, height: usize, mines: u32) -> Field {
+        Field {
+            width,
+            height,
+            mines,
+            cells: vec![Cell::new(); width * height],
+            flags_left: mines,
+            rules: Rules::default(),
+        }
+    }
+
+    // Past about a third of the board mines can't all be spread out, so they start ending up next to each other
+    pub fn max_mines(width: usize, height: usize) -> u32 {
+        (width * height / 3) as u32
+    }
+
+    // Where the cell is on its own layer
+    pub fn index_to_x_y(&self, index: usize) -> (usize, usize) {
+        let index = index % (self.width * self.height);
+        (index % self.width, index / self.width)
+    }
+
+    // Index of the cell on the first layer, add layer_start for the others
+    pub fn x_y_to_index(&self, x: usize, y: usize) -> usize {
+        y * self.width + x
+    }
+
+    pub fn layer(&self, index: usize) -> usize {
+        index / (self.width * self.height)
+    }
+
+    pub fn layer_start(&self, layer: usize) -> usize {
+        layer * self.width * self.height
+    }
+
+    // Every neighbour goes through this, counting, flood fill and chording alike
+    pub fn topology(&self) -> Box<dyn Topology> {
+        let (width, height, neighbourhood) = (self.width, self.height, self.rules.neighbourhood);
+        match self.rules.shape {
+            Shape::Rectangle => Box::new(Rectangle { width, height, neighbourhood }),
+            Shape::Torus => Box::new(Torus { width, height, neighbourhood }),
+            Shape::Hex => Box::new(Hex { width, height }),
+            Shape::Layers => Box::new(Layered { width, height, layers: self.rules.layers, neighbourhood }),
+        }
+    }
+
+    // The cells touching this one
+    pub fn surrounding(&self, index: usize) -> Vec<usize> {
+        let mut neighbours = Vec::<usize>::new();
+        self.topology().neighbours(index, &mut neighbours);
+        neighbours
+    }
+
+    // Exploded mines count as found, cells with more than one mine need a flag for each
+    pub fn all_mines_flagged(&self) -> bool {
+        self.cells.iter().all(|cell| !cell.has_mine() || cell.flags == cell.mines || cell.revealed)
+    }
+
+    // Won once every flag is down and they're all on mines
+    pub fn is_cleared(&self) -> bool {
+        self.flags_left == 0 && self.all_mines_flagged()
+    }
+
+    pub fn cycle_flag(&mut self, index: usize, question_marks: bool) -> Option<bool> {
+        self.cells[index].cycle_flag(self.rules.mines_per_cell, &mut self.flags_left, question_marks)
+    }
+
+    // A mine went off. With a life to spend the mine stays revealed and counts as found, without one the game is lost.
+    // Returns None when the game is lost, otherwise whether that was the last mine to find
+    pub fn explode(&mut self, index: usize, lives: &mut u32) -> Option<bool> {
+        if *lives == 0 {
+            return None;
+        }
+        *lives -= 1;
+        let cell = &mut self.cells[index];
+        cell.revealed = true;
+        cell.questioned = false;
+        self.flags_left = (self.flags_left + u32::from(cell.flags)).saturating_sub(u32::from(cell.mines));
+        cell.flags = 0;
+        Some(self.is_cleared())
+    }
+
+    // Reveals outwards from a cell with no mines around it, returns the cells it revealed in the order they were revealed
+    pub fn reveal_surrounding_mines_from_index(&mut self, index: usize) -> Vec<usize> {
+        let topology = self.topology();
+        let mut revealed = Vec::<usize>::new();
+        let mut neighbours = Vec::<usize>::new();
+        // Empty cells whose neighbours still need looking at, each one only ever goes in once as it's revealed
+        let mut to_visit = vec![index];
+        while let Some(index) = to_visit.pop() {
+            neighbours.clear();
+            topology.neighbours(index, &mut neighbours);
+            for neighbour in &neighbours {
+                let cell = &mut self.cells[*neighbour];
+                if cell.should_reveal() {
+                    cell.reveal(|flags| self.flags_left += u32::from(flags), || {});
+                    revealed.push(*neighbour);
+                    if cell.mines_around == 0 {
+                        to_visit.push(*neighbour);
+                    }
+                }
+            }
+        }
+        revealed
+    }
+}
+
+// Times flood fills on big boards with hardly any mines, where nearly every cell is revealed at once
+pub fn benchmark() {
+    const RUNS: u32 = 5;
+    for (width, height, mines) in [(100, 100, 10), (1_000, 1_000, 100), (2_000, 2_000, 100)] {
+        let mut total = Duration::ZERO;
+        let mut revealed = 0;
+        let mut runs = 0;
+        for _ in 0..RUNS {
+            let mut field = Field::new(width, height, mines, Rules::default());
+            let Some(start) = field.cells.iter().position(|cell| !cell.has_mine() && cell.mines_around == 0) else {
+                continue;
+            };
+            field.cells[start].revealed = true;
+            let started = Instant::now();
+            revealed = field.reveal_surrounding_mines_from_index(start).len();
+            total += started.elapsed();
+            runs += 1;
+        }
+        // Boards without an empty cell to start from are skipped
+        if runs == 0 {
+            println!("{}x{} with {} mines: no empty cell to start from", width, height, mines);
+            continue;
+        }
+        println!("{}x{} with {} mines: {} cells revealed in {:?} on average", width, height, mines, revealed, total / runs);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::topology::{DEFAULT_LAYERS, NEIGHBOURHOODS, SHAPES};
+
+    // Every cell that was hidden before and revealed after, each only once
+    fn check_flood(field: &mut Field, start: usize) {
+        let before: Vec<bool> = field.cells.iter().map(|cell| cell.revealed).collect();
+        let revealed = field.reveal_surrounding_mines_from_index(start);
+        let mut changed: Vec<usize> = (0..field.cells.len()).filter(|i| !before[*i] && field.cells[*i].revealed).collect();
+        let mut returned = revealed.clone();
+        returned.sort_unstable();
+        changed.sort_unstable();
+        assert_eq!(returned, changed);
+        assert!(returned.windows(2).all(|pair| pair[0] != pair[1]));
+        assert!(!revealed.contains(&start));
+    }
+
+    // Deep enough that a recursive flood fill would run out of stack
+    #[test]
+    fn floods_huge_empty_board() {
+        let mut field = Field::new(1_000, 1_000, 0, Rules::default());
+        field.cells[0].revealed = true;
+        let revealed = field.reveal_surrounding_mines_from_index(0);
+        assert_eq!(revealed.len(), field.cells.len() - 1);
+        assert!(field.cells.iter().all(|cell| cell.revealed));
+    }
+
+    #[test]
+    fn floods_only_newly_revealed_cells() {
+        for _ in 0..20 {
+            let mut field = Field::new(60, 40, 40, Rules::default());
+            // Some cells revealed already, which shouldn't come back
+            for i in (0..field.cells.len()).step_by(7) {
+                if !field.cells[i].has_mine() {
+                    field.cells[i].revealed = true;
+                }
+            }
+            let Some(start) = field.cells.iter().position(|cell| !cell.has_mine() && cell.mines_around == 0 && !cell.revealed) else {
+                continue;
+            };
+            field.cells[start].revealed = true;
+            check_flood(&mut field, start);
+        }
+    }
+
+    // Small boards at the most mines the custom board allows used to run out of places to put them
+    #[test]
+    fn fills_small_boards_to_max_mines() {
+        for (width, height) in [(5, 5), (5, 6), (9, 9)] {
+            let mines = Field::max_mines(width, height);
+            for shape in SHAPES {
+                for neighbourhood in NEIGHBOURHOODS {
+                    for mines_per_cell in 1..=MAX_MINES_PER_CELL {
+                        let rules = Rules { shape, layers: DEFAULT_LAYERS, neighbourhood, mines_per_cell };
+                        for _ in 0..20 {
+                            let field = Field::new(width, height, mines, rules);
+                            assert_eq!(field.mines, mines);
+                            assert_eq!(field.flags_left, mines);
+                            assert_eq!(field.cells.iter().map(|cell| u32::from(cell.mines)).sum::<u32>(), mines);
+                            assert!(field.cells.iter().all(|cell| cell.mines <= mines_per_cell));
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn clamps_mines_to_what_fits() {
+        let field = Field::new(5, 5, 100, Rules { mines_per_cell: 2, ..Rules::default() });
+        assert_eq!(field.mines, 50);
+        assert!(field.cells.iter().all(|cell| cell.mines == 2));
+    }
+
+    // A 3 by 1 board with the given mines in each cell
+    fn row(mines: [u8; 3], mines_per_cell: u8) -> Field {
+        let mut field = Field::empty(3, 1, mines.iter().map(|mines| u32::from(*mines)).sum());
+        field.rules.mines_per_cell = mines_per_cell;
+        for (cell, mines) in field.cells.iter_mut().zip(mines) {
+            cell.mines = mines;
+        }
+        field
+    }
+
+    #[test]
+    fn single_flags_cycle() {
+        let mut field = row([1, 0, 1], 1);
+        assert_eq!(field.cycle_flag(0, false), Some(true));
+        assert_eq!((field.cells[0].flags, field.flags_left), (1, 1));
+        assert_eq!(field.cycle_flag(0, false), Some(false));
+        assert_eq!((field.cells[0].flags, field.flags_left), (0, 2));
+        // With question marks a flag turns into one before clearing
+        field.cycle_flag(0, true);
+        assert_eq!(field.cycle_flag(0, true), Some(false));
+        assert!(field.cells[0].questioned && field.flags_left == 2);
+        assert_eq!(field.cycle_flag(0, true), Some(false));
+        assert!(!field.cells[0].questioned);
+        field.cells[1].revealed = true;
+        assert_eq!(field.cycle_flag(1, false), None);
+    }
+
+    #[test]
+    fn stacked_flags_cycle() {
+        let mut field = row([3, 1, 0], 3);
+        for flags in 1..=3 {
+            assert_eq!(field.cycle_flag(0, false), Some(true));
+            assert_eq!((field.cells[0].flags, field.flags_left), (flags, 4 - u32::from(flags)));
+        }
+        // Full, so the next press takes every flag off
+        assert_eq!(field.cycle_flag(0, false), Some(false));
+        assert_eq!((field.cells[0].flags, field.flags_left), (0, 4));
+    }
+
+    #[test]
+    fn running_out_of_flags_clears_the_cell() {
+        let mut field = row([2, 0, 0], 3);
+        field.cycle_flag(1, false);
+        field.cycle_flag(0, false);
+        assert_eq!(field.flags_left, 0);
+        assert_eq!(field.cycle_flag(2, false), None);
+        assert_eq!(field.cycle_flag(0, false), Some(false));
+        assert_eq!((field.cells[0].flags, field.flags_left), (0, 1));
+    }
+
+    #[test]
+    fn cleared_once_every_mine_is_flagged() {
+        let mut field = row([1, 0, 1], 1);
+        field.cycle_flag(0, false);
+        assert!(!field.is_cleared());
+        field.cycle_flag(2, false);
+        assert!(field.is_cleared());
+        // A flag in the wrong place leaves a mine unflagged
+        let mut field = row([1, 0, 1], 1);
+        field.cycle_flag(0, false);
+        field.cycle_flag(1, false);
+        assert!(!field.is_cleared());
+    }
+
+    #[test]
+    fn stacked_mines_need_a_flag_each() {
+        let mut field = row([3, 0, 1], 3);
+        field.cycle_flag(2, false);
+        for _ in 0..2 {
+            field.cycle_flag(0, false);
+            assert!(!field.is_cleared());
+        }
+        field.cycle_flag(0, false);
+        assert!(field.is_cleared());
+        // Too many flags on one cell and too few on another still uses them all up
+        let mut field = row([2, 0, 2], 3);
+        for _ in 0..3 {
+            field.cycle_flag(0, false);
+        }
+        field.cycle_flag(2, false);
+        assert_eq!(field.flags_left, 0);
+        assert!(!field.is_cleared());
+    }
+
+    #[test]
+    fn exploding_spends_a_life() {
+        let mut field = row([1, 0, 1], 1);
+        let mut lives = 2;
+        assert_eq!(field.explode(0, &mut lives), Some(false));
+        assert_eq!((lives, field.flags_left), (1, 1));
+        assert!(field.cells[0].revealed);
+        // The flag on the mine comes back before the mine is taken off
+        field.cycle_flag(2, false);
+        assert_eq!(field.explode(2, &mut lives), Some(true));
+        assert_eq!((lives, field.flags_left), (0, 0));
+        assert_eq!(field.cells[2].flags, 0);
+    }
+
+    #[test]
+    fn exploded_mines_count_towards_the_win() {
+        let mut field = row([3, 0, 1], 3);
+        field.cycle_flag(2, false);
+        field.cycle_flag(0, false);
+        let mut lives = 1;
+        assert_eq!(field.explode(0, &mut lives), Some(true));
+        assert!(field.is_cleared());
+    }
+
+    #[test]
+    fn last_life_loses() {
+        let mut field = row([1, 0, 1], 1);
+        let mut lives = 1;
+        field.explode(0, &mut lives);
+        assert_eq!(field.explode(2, &mut lives), None);
+        assert_eq!((lives, field.flags_left), (0, 1));
+        assert!(!field.cells[2].revealed);
+        assert_eq!(row([1, 0, 0], 1).explode(0, &mut 0), None);
+    }
+
+    #[test]
+    fn flood_never_reveals_mines() {
+        let mut field = Field::new(30, 16, 99, Rules::default());
+        if let Some(start) = field.cells.iter().position(|cell| !cell.has_mine() && cell.mines_around == 0) {
+            field.cells[start].revealed = true;
+            field.reveal_surrounding_mines_from_index(start);
+            assert!(field.cells.iter().all(|cell| !(cell.revealed && cell.has_mine())));
+        }
+    }
+}
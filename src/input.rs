@@ -0,0 +1,443 @@
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use sdl2::controller::{Axis, Button};
+use sdl2::keyboard::Keycode;
+use serde::{Deserialize, Deserializer, Serialize};
+
+// Fraction of the stick's travel that is ignored
+const STICK_DEADZONE: f32 = 0.25;
+// Cells per second just outside the deadzone and at full tilt
+const STICK_MIN_SPEED: f32 = 4.0;
+const STICK_MAX_SPEED: f32 = 20.0;
+
+// Held moves speed up over this long, to this many times the repeat rate
+const ACCELERATION_TIME: f32 = 1.5;
+const MAX_ACCELERATION: f32 = 3.0;
+
+// Triggers are axes, these turn them into presses with a bit of hysteresis
+const TRIGGER_PRESS: i16 = 16_000;
+const TRIGGER_RELEASE: i16 = 8_000;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    MoveRight,
+    MoveLeft,
+    MoveUp,
+    MoveDown,
+    JumpLeft,
+    JumpRight,
+    // Only on 3D boards
+    LayerUp,
+    LayerDown,
+    // Also confirms in menus
+    Reveal,
+    // Also goes back in menus
+    Flag,
+    Chord,
+    Select,
+    Pause,
+    // Turns all sound on and off
+    Mute,
+    // Pauses and resumes the music
+    PlayPause,
+    NextTrack,
+}
+
+pub const ACTIONS: [Action; 16] = [
+    Action::MoveRight,
+    Action::MoveLeft,
+    Action::MoveUp,
+    Action::MoveDown,
+    Action::JumpLeft,
+    Action::JumpRight,
+    Action::LayerUp,
+    Action::LayerDown,
+    Action::Reveal,
+    Action::Flag,
+    Action::Chord,
+    Action::Select,
+    Action::Pause,
+    Action::Mute,
+    Action::PlayPause,
+    Action::NextTrack,
+];
+
+impl Action {
+    pub fn name(self) -> &'static str {
+        match self {
+            Action::MoveRight => "Move Right",
+            Action::MoveLeft => "Move Left",
+            Action::MoveUp => "Move Up",
+            Action::MoveDown => "Move Down",
+            Action::JumpLeft => "Jump Left",
+            Action::JumpRight => "Jump Right",
+            Action::LayerUp => "Layer Up",
+            Action::LayerDown => "Layer Down",
+            Action::Reveal => "Reveal",
+            Action::Flag => "Flag",
+            Action::Chord => "Chord",
+            Action::Select => "Select",
+            Action::Pause => "Pause",
+            Action::Mute => "Mute",
+            Action::PlayPause => "Play/Pause Music",
+            Action::NextTrack => "Next Track",
+        }
+    }
+
+    // What the action is called in the config file
+    pub fn config_name(self) -> &'static str {
+        match self {
+            Action::MoveRight => "move_right",
+            Action::MoveLeft => "move_left",
+            Action::MoveUp => "move_up",
+            Action::MoveDown => "move_down",
+            Action::JumpLeft => "jump_left",
+            Action::JumpRight => "jump_right",
+            Action::LayerUp => "layer_up",
+            Action::LayerDown => "layer_down",
+            Action::Reveal => "reveal",
+            Action::Flag => "flag",
+            Action::Chord => "chord",
+            Action::Select => "select",
+            Action::Pause => "pause",
+            Action::Mute => "mute",
+            Action::PlayPause => "play_pause",
+            Action::NextTrack => "next_track",
+        }
+    }
+
+    // Movement acts as soon as it's pressed and repeats while held, everything else acts on release
+    pub fn is_movement(self) -> bool {
+        matches!(self, Action::MoveRight | Action::MoveLeft | Action::MoveUp | Action::MoveDown | Action::JumpLeft | Action::JumpRight | Action::LayerUp | Action::LayerDown)
+    }
+}
+
+// Names of the keys, or controller buttons, bound to each action as SDL spells them
+#[derive(Clone, Serialize)]
+pub struct Bindings {
+    pub move_right: Vec<String>,
+    pub move_left: Vec<String>,
+    pub move_up: Vec<String>,
+    pub move_down: Vec<String>,
+    pub jump_left: Vec<String>,
+    pub jump_right: Vec<String>,
+    pub layer_up: Vec<String>,
+    pub layer_down: Vec<String>,
+    pub reveal: Vec<String>,
+    pub flag: Vec<String>,
+    pub chord: Vec<String>,
+    pub select: Vec<String>,
+    pub pause: Vec<String>,
+    pub mute: Vec<String>,
+    pub play_pause: Vec<String>,
+    pub next_track: Vec<String>,
+}
+
+fn names(names: &[&str]) -> Vec<String> {
+    names.iter().map(|name| name.to_string()).collect()
+}
+
+impl Bindings {
+    pub fn keyboard() -> Bindings {
+        Bindings {
+            move_right: names(&["D", "Right"]),
+            move_left: names(&["A", "Left"]),
+            move_up: names(&["W", "Up"]),
+            move_down: names(&["S", "Down"]),
+            jump_left: names(&["Q"]),
+            jump_right: names(&["E"]),
+            layer_up: names(&["PageUp"]),
+            layer_down: names(&["PageDown"]),
+            reveal: names(&["U", "Space", "Return"]),
+            flag: names(&["H", "F"]),
+            chord: names(&["J", "C"]),
+            select: names(&["B"]),
+            pause: names(&["N", "Escape", "P"]),
+            mute: names(&["M"]),
+            play_pause: names(&["K"]),
+            next_track: names(&["L"]),
+        }
+    }
+
+    pub fn controller() -> Bindings {
+        Bindings {
+            move_right: names(&["dpright"]),
+            move_left: names(&["dpleft"]),
+            move_up: names(&["dpup"]),
+            move_down: names(&["dpdown"]),
+            jump_left: names(&["leftshoulder"]),
+            jump_right: names(&["rightshoulder"]),
+            // The shoulders change layers on 3D boards
+            layer_up: Vec::new(),
+            layer_down: Vec::new(),
+            reveal: names(&["b"]),
+            flag: names(&["a"]),
+            chord: names(&["y", "lefttrigger", "righttrigger"]),
+            select: names(&["back"]),
+            pause: names(&["start"]),
+            mute: Vec::new(),
+            play_pause: Vec::new(),
+            next_track: Vec::new(),
+        }
+    }
+
+    pub fn get(&self, action: Action) -> &Vec<String> {
+        match action {
+            Action::MoveRight => &self.move_right,
+            Action::MoveLeft => &self.move_left,
+            Action::MoveUp => &self.move_up,
+            Action::MoveDown => &self.move_down,
+            Action::JumpLeft => &self.jump_left,
+            Action::JumpRight => &self.jump_right,
+            Action::LayerUp => &self.layer_up,
+            Action::LayerDown => &self.layer_down,
+            Action::Reveal => &self.reveal,
+            Action::Flag => &self.flag,
+            Action::Chord => &self.chord,
+            Action::Select => &self.select,
+            Action::Pause => &self.pause,
+            Action::Mute => &self.mute,
+            Action::PlayPause => &self.play_pause,
+            Action::NextTrack => &self.next_track,
+        }
+    }
+
+    pub fn get_mut(&mut self, action: Action) -> &mut Vec<String> {
+        match action {
+            Action::MoveRight => &mut self.move_right,
+            Action::MoveLeft => &mut self.move_left,
+            Action::MoveUp => &mut self.move_up,
+            Action::MoveDown => &mut self.move_down,
+            Action::JumpLeft => &mut self.jump_left,
+            Action::JumpRight => &mut self.jump_right,
+            Action::LayerUp => &mut self.layer_up,
+            Action::LayerDown => &mut self.layer_down,
+            Action::Reveal => &mut self.reveal,
+            Action::Flag => &mut self.flag,
+            Action::Chord => &mut self.chord,
+            Action::Select => &mut self.select,
+            Action::Pause => &mut self.pause,
+            Action::Mute => &mut self.mute,
+            Action::PlayPause => &mut self.play_pause,
+            Action::NextTrack => &mut self.next_track,
+        }
+    }
+
+    // Actions the config leaves out keep their bindings from defaults, rather than ending up with none
+    fn merged<'de, D: Deserializer<'de>>(mut defaults: Bindings, deserializer: D) -> Result<Bindings, D::Error> {
+        let mut bound = HashMap::<String, Vec<String>>::deserialize(deserializer)?;
+        for action in ACTIONS {
+            if let Some(names) = bound.remove(action.config_name()) {
+                *defaults.get_mut(action) = names;
+            }
+        }
+        Ok(defaults)
+    }
+
+    pub fn action(&self, name: &str) -> Option<Action> {
+        ACTIONS.into_iter().find(|action| self.get(*action).iter().any(|bound| bound.eq_ignore_ascii_case(name)))
+    }
+
+    // Adds the binding to the action, taking it away from whichever action had it before
+    pub fn bind(&mut self, action: Action, name: String) {
+        for other in ACTIONS {
+            self.get_mut(other).retain(|bound| !bound.eq_ignore_ascii_case(&name));
+        }
+        self.get_mut(action).push(name);
+    }
+}
+
+pub fn deserialize_keyboard<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Bindings, D::Error> {
+    Bindings::merged(Bindings::keyboard(), deserializer)
+}
+
+pub fn deserialize_controller<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Bindings, D::Error> {
+    Bindings::merged(Bindings::controller(), deserializer)
+}
+
+pub fn key_action(bindings: &Bindings, keycode: Keycode) -> Option<Action> {
+    bindings.action(&keycode.name())
+}
+
+pub fn button_action(bindings: &Bindings, button: Button) -> Option<Action> {
+    bindings.action(&button.string())
+}
+
+#[derive(Clone, Copy)]
+pub struct HeldInput {
+    since: Instant,
+    repeats: u32,
+}
+
+impl HeldInput {
+    pub fn new() -> HeldInput {
+        HeldInput {
+            since: Instant::now(),
+            repeats: 0,
+        }
+    }
+
+    // Repeats owed since the last call, going by how long the input has been held rather than by frames
+    pub fn repeats_due(&mut self, delay: Duration, rate: u32) -> u32 {
+        self.repeats_after(self.since.elapsed(), delay, rate)
+    }
+
+    fn repeats_after(&mut self, held: Duration, delay: Duration, rate: u32) -> u32 {
+        let Some(held) = held.checked_sub(delay) else {
+            return 0;
+        };
+        let held = held.as_secs_f32();
+        // Number of repeats so far is the rate integrated over the hold, the rate climbs linearly until ACCELERATION_TIME
+        let ramp = held.min(ACCELERATION_TIME);
+        let distance = ramp + (MAX_ACCELERATION - 1.0) * ramp * ramp / (2.0 * ACCELERATION_TIME) + MAX_ACCELERATION * (held - ramp);
+        // The first repeat happens right when the delay runs out
+        let total = (distance * rate as f32) as u32 + 1;
+        let due = total.saturating_sub(self.repeats);
+        self.repeats = total.max(self.repeats);
+        due
+    }
+}
+
+// Triggers share the button bindings, under SDL's axis names
+pub fn axis_action(bindings: &Bindings, axis: Axis) -> Option<Action> {
+    bindings.action(&axis.string())
+}
+
+#[derive(Clone, Copy, Default)]
+struct StickAxis {
+    value: i16,
+    // Whether the stick is outside the deadzone, and which way
+    direction: i16,
+    // Cells travelled towards the next step
+    progress: f32,
+}
+
+impl StickAxis {
+    fn steps(&mut self, elapsed: f32) -> i32 {
+        let magnitude = (self.value as f32).abs() / i16::MAX as f32;
+        if magnitude < STICK_DEADZONE {
+            self.direction = 0;
+            return 0;
+        }
+        // Leaving the deadzone, or reversing, moves straight away
+        if self.direction != self.value.signum() {
+            self.direction = self.value.signum();
+            self.progress = 0.0;
+            return self.direction as i32;
+        }
+        let speed = STICK_MIN_SPEED + (STICK_MAX_SPEED - STICK_MIN_SPEED) * (magnitude - STICK_DEADZONE) / (1.0 - STICK_DEADZONE);
+        self.progress += speed * elapsed;
+        let steps = self.progress.trunc();
+        self.progress -= steps;
+        steps as i32 * self.direction as i32
+    }
+
+    // One step as the stick leaves the deadzone and none while it's held there
+    fn flick(&mut self) -> i32 {
+        let direction = if (self.value as f32).abs() / (i16::MAX as f32) < STICK_DEADZONE { 0 } else { self.value.signum() };
+        let step = if direction != 0 && direction != self.direction { direction as i32 } else { 0 };
+        self.direction = direction;
+        step
+    }
+}
+
+// Analog state from every connected controller
+#[derive(Clone, Copy, Default)]
+pub struct ControllerState {
+    left_x: StickAxis,
+    left_y: StickAxis,
+    triggers_down: [bool; 2],
+    last_update: Option<Instant>,
+}
+
+impl ControllerState {
+    // Returns whether a trigger was pressed or released by this motion
+    pub fn axis_motion(&mut self, axis: Axis, value: i16) -> Option<bool> {
+        let trigger = match axis {
+            Axis::LeftX => {
+                self.left_x.value = value;
+                return None;
+            },
+            Axis::LeftY => {
+                self.left_y.value = value;
+                return None;
+            },
+            Axis::TriggerLeft => 0,
+            Axis::TriggerRight => 1,
+            _ => return None,
+        };
+        let was_down = self.triggers_down[trigger];
+        if !was_down && value >= TRIGGER_PRESS {
+            self.triggers_down[trigger] = true;
+        } else if was_down && value <= TRIGGER_RELEASE {
+            self.triggers_down[trigger] = false;
+        } else {
+            return None;
+        }
+        Some(self.triggers_down[trigger])
+    }
+
+    // Cells the left stick moved the cursor since the last call
+    pub fn stick_steps(&mut self) -> (i32, i32) {
+        let now = Instant::now();
+        let elapsed = self.last_update.map_or(0.0, |last_update| (now - last_update).as_secs_f32());
+        self.last_update = Some(now);
+        (self.left_x.steps(elapsed), self.left_y.steps(elapsed))
+    }
+
+    // Rows the left stick moved a menu selection since the last call
+    pub fn menu_steps(&mut self) -> i32 {
+        self.last_update = Some(Instant::now());
+        self.left_y.flick()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DELAY: Duration = Duration::from_millis(250);
+    const RATE: u32 = 10;
+
+    fn total_repeats(held: &[u64]) -> u32 {
+        let mut input = HeldInput::new();
+        held.iter().map(|held| input.repeats_after(Duration::from_millis(*held), DELAY, RATE)).sum()
+    }
+
+    #[test]
+    fn nothing_before_the_delay() {
+        assert_eq!(total_repeats(&[0, 100, 249]), 0);
+    }
+
+    #[test]
+    fn first_repeat_when_the_delay_runs_out() {
+        assert_eq!(total_repeats(&[250]), 1);
+        // Each repeat is only owed once
+        assert_eq!(total_repeats(&[250, 260, 270]), 1);
+    }
+
+    #[test]
+    fn repeats_at_the_rate_to_begin_with() {
+        // A tenth of a second into the hold is a little under one repeat at 10 a second, speeding up makes it a little over
+        assert_eq!(total_repeats(&[350]), 2);
+        assert_eq!(total_repeats(&[340]), 1);
+    }
+
+    #[test]
+    fn speeds_up_to_the_most_acceleration() {
+        let held = |seconds: f32| 250 + (seconds * 1_000.0) as u64;
+        let ramped = total_repeats(&[held(ACCELERATION_TIME)]);
+        // The ramp averages out halfway between the rate and the most acceleration
+        let expected = (ACCELERATION_TIME * (1.0 + MAX_ACCELERATION) / 2.0 * RATE as f32) as u32 + 1;
+        assert_eq!(ramped, expected);
+        // After the ramp every second adds MAX_ACCELERATION times the rate
+        assert_eq!(total_repeats(&[held(ACCELERATION_TIME + 1.0)]) - ramped, (MAX_ACCELERATION * RATE as f32) as u32);
+    }
+
+    #[test]
+    fn same_total_however_often_it_is_asked() {
+        let every_frame: Vec<u64> = (0..=3_000).step_by(16).collect();
+        assert_eq!(total_repeats(&every_frame), total_repeats(&[3_000]));
+    }
+}
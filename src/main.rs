@@ -1,451 +1,869 @@
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::ops::Range;
 use std::thread;
 use std::time::{Instant, Duration};
 
 use sdl2::audio::{AudioSpecDesired, AudioQueue};
-use sdl2::controller::Button;
-use sdl2::event::Event;
-use sdl2::image::LoadTexture;
+use sdl2::controller::GameController;
+use sdl2::event::{Event, WindowEvent};
 use sdl2::keyboard::Keycode;
 use sdl2::mixer::{InitFlag, DEFAULT_CHANNELS, DEFAULT_FORMAT, DEFAULT_FREQUENCY};
 use sdl2::mouse::MouseButton;
 use sdl2::pixels::Color;
-use sdl2::rect::Rect;
-use sdl2::render::{BlendMode, Canvas, Texture};
+use sdl2::rect::{Point, Rect};
+use sdl2::render::{BlendMode, Canvas};
 use sdl2::rwops::RWops;
 use sdl2::ttf::Font;
-use sdl2::video::Window;
+use sdl2::video::{FullscreenType, Window};
+
+mod animation;
+mod announce;
+mod clock;
+mod config;
+mod endless;
+mod field;
+mod input;
+mod menu;
+mod music;
+mod net;
+mod palette;
+mod sound;
+mod synth;
+mod theme;
+mod topology;
+
+use animation::Animations;
+use announce::Announcer;
+use clock::Clock;
+use config::Config;
+use endless::Endless;
+use field::{Field, Rules, MAX_MINES_PER_CELL};
+use input::{Action, ControllerState, HeldInput, ACTIONS};
+use music::MusicPlayer;
+use menu::{Difficulty, Scene, Statistics, CUSTOM_DIFFICULTY, DIFFICULTIES};
+use net::{CellState, Client, ClientMessage, ServerMessage, ServerOptions};
+use palette::Colors;
+use sound::{Sound, SoundEffects};
+use synth::Synth;
+use theme::{Theme, Tile};
+use topology::{Shape, DEFAULT_LAYERS, NEIGHBOURHOODS, SHAPES};
 
 const NUMBER_OF_ROWS_AND_COLUMNS: usize = 24;
-const NUMBER_OF_CELLS: usize = NUMBER_OF_ROWS_AND_COLUMNS * NUMBER_OF_ROWS_AND_COLUMNS;
+const NUMBER_OF_MINES: u32 = 99;
 
+const MULTIPLAYER_COLUMNS: usize = 40;
+const MULTIPLAYER_ROWS: usize = 24;
+const MULTIPLAYER_MINES: u32 = 180;
+
+// Cells the shoulder buttons move the cursor
+const JUMP_DISTANCE: i32 = 5;
 const HEIGHT_PLAY_AREA_START: u16 = 64;
-const WIDTH: u16 = 32 * (NUMBER_OF_ROWS_AND_COLUMNS as u16);
-const HEIGHT: u16 = HEIGHT_PLAY_AREA_START + (32 * NUMBER_OF_ROWS_AND_COLUMNS as u16);
 
-const fn one_d_to_two_d(coord: usize) -> (usize, usize) {
-    (coord % NUMBER_OF_ROWS_AND_COLUMNS, coord / NUMBER_OF_ROWS_AND_COLUMNS)
-}
+const MENU_WIDTH: u32 = 768;
+const MENU_HEIGHT: u32 = 832;
+const MIN_WIDTH: u32 = 384;
+const MIN_HEIGHT: u32 = 512;
 
-/*
-const fn one_d_to_two_d_x(coord: usize) -> usize {
-    coord % NUMBER_OF_ROWS_AND_COLUMNS
-}
+// Reveals that open up at least this many cells get the cascade sound
+const CASCADE_SIZE: usize = 10;
 
-const fn one_d_to_two_d_y(coord: usize) -> usize {
-    coord / NUMBER_OF_ROWS_AND_COLUMNS
-}
-*/
+// Added to the time for each life lost
+const LIFE_PENALTY: Duration = Duration::from_secs(30);
 
-const fn two_d_to_one_d(x: usize, y: usize) -> usize {
-    y * NUMBER_OF_ROWS_AND_COLUMNS + x
-}
+// Numbers drawn with the font when the palette doesn't set its own colours
+const NUMBER_COLORS: [Color; 8] = [Color::RGB(0, 0, 255), Color::RGB(0, 128, 0), Color::RGB(255, 0, 0), Color::RGB(0, 0, 128), Color::RGB(128, 0, 0), Color::RGB(0, 128, 128), Color::RGB(0, 0, 0), Color::RGB(128, 128, 128)];
 
-const SAMPLE_RATE: u16 = 44_100;
+const PLAYER_COLORS: [(u8, u8, u8); 8] = [(230, 25, 75), (60, 180, 75), (0, 130, 200), (245, 130, 48), (145, 30, 180), (70, 240, 240), (240, 50, 230), (210, 245, 60)];
 
-const DEBUG_MINE: &[u8; 210] = include_bytes!("../assets/debug_mine.png");
-const FLAGGED_MINE: &[u8; 211] = include_bytes!("../assets/flagged_mine.png");
-const UNFLAGGED_MINE: &[u8; 128] = include_bytes!("../assets/unflagged_mine.png");
-const REVEALED_MINE: &[u8; 128] = include_bytes!("../assets/revealed_mine.png");
-const CURSOR: &[u8; 129] = include_bytes!("../assets/cursor.png");
-
-const REVEALED_MINE_1: &[u8; 253] = include_bytes!("../assets/revealed_mine_1.png");
-const REVEALED_MINE_2: &[u8; 583] = include_bytes!("../assets/revealed_mine_2.png");
-const REVEALED_MINE_3: &[u8; 639] = include_bytes!("../assets/revealed_mine_3.png");
-const REVEALED_MINE_4: &[u8; 410] = include_bytes!("../assets/revealed_mine_4.png");
-const REVEALED_MINE_5: &[u8; 509] = include_bytes!("../assets/revealed_mine_5.png");
-const REVEALED_MINE_6: &[u8; 736] = include_bytes!("../assets/revealed_mine_6.png");
-const REVEALED_MINE_7: &[u8; 427] = include_bytes!("../assets/revealed_mine_7.png");
-const REVEALED_MINE_8: &[u8; 792] = include_bytes!("../assets/revealed_mine_8.png");
+const SAMPLE_RATE: u16 = 44_100;
 
 const MOULDY_CHEESE_REGULAR: &[u8; 112116] = include_bytes!("../assets/MouldyCheeseRegular-WyMWG.ttf");
 
-const AWAKE10_MEGA_WALL: &[u8; 2026231] = include_bytes!("../assets/awake10_megaWall.mp3");
 
+// A mouse button held down over the board, it only acts if released over the same cell
 #[derive(Clone, Copy)]
-struct Cell {
-    revealed: bool,
-    flagged: bool,
-    has_mine: bool,
-    mines_around: u8,
+struct MousePress {
+    button: MouseButton,
+    index: usize,
+    // Whether the mouse is still over the cell it was pressed on
+    inside: bool,
+}
+
+struct Game {
+    scene: Scene,
+    was_winner: bool,
+    should_die: bool,
+    // Every mine revealed by the last action, a chord can set off more than one
+    hit_mines: Vec<usize>,
+    // Mines that can still be hit without losing, shared between everyone in multiplayer
+    lives: u32,
+    lives_used: u32,
+    clock: Clock,
+    game_duration: Option<Duration>,
+    field: Field,
+    current_selection: usize,
+    inputs: [Option<HeldInput>; ACTIONS.len()],
+    // Sticks and triggers of each controller, by instance id
+    controller_states: HashMap<u32, ControllerState>,
+    // Comes from the theme
+    cell_size: u32,
+    mouse_press: Option<MousePress>,
+    // Set when playing on someone else's server, the server owns the real field
+    remote: Option<Client>,
+    // Set when playing an endless board, the field is the part of it in view
+    endless: Option<Endless>,
+    // Given with --seed, every endless game is made from it instead of a random one
+    endless_seed: Option<u64>,
+    // Index into DIFFICULTIES, or CUSTOM_DIFFICULTY, of the board being played
+    difficulty: usize,
+    // Difficulty shown on the main menu
+    menu_difficulty: usize,
+    custom: Difficulty,
+    menu_selection: usize,
+    // Action waiting for a key or button on the key bindings screen
+    rebinding: Option<Action>,
+    // Scene the settings return to, they can be opened from the menu or while paused
+    settings_return: Scene,
+    statistics: Statistics,
+    config: Config,
+    // Cascades, flags and explosions being drawn
+    animations: Animations,
+    // Sound effects waiting to be played this frame
+    sounds: Vec<Sound>,
+    // Asks the music player to move on to the next track
+    skip_track: bool,
+    should_quit: bool,
 }
 
-impl Cell {
-    fn new() -> Cell {
-        Cell {
-            revealed: false,
-            flagged: false,
-            has_mine: false,
-            mines_around: 0,
+impl Game {
+    fn new(field: Field) -> Game {
+        Game {
+            scene: Scene::Playing,
+            was_winner: false,
+            should_die: false,
+            hit_mines: Vec::new(),
+            lives: 0,
+            lives_used: 0,
+            clock: Clock::default(),
+            game_duration: None,
+            current_selection: 0,
+            inputs: [None; ACTIONS.len()],
+            controller_states: HashMap::new(),
+            cell_size: theme::DEFAULT_TILE_SIZE,
+            mouse_press: None,
+            remote: None,
+            endless: None,
+            endless_seed: None,
+            difficulty: CUSTOM_DIFFICULTY,
+            menu_difficulty: DIFFICULTIES.len() - 1,
+            custom: Difficulty {
+                name: "Custom",
+                width: field.width,
+                height: field.height,
+                mines: field.mines,
+            },
+            field,
+            menu_selection: 0,
+            rebinding: None,
+            settings_return: Scene::Menu,
+            statistics: Statistics::default(),
+            config: Config::default(),
+            animations: Animations::default(),
+            sounds: Vec::new(),
+            skip_track: false,
+            should_quit: false,
         }
     }
 
-    fn should_reveal(&self) -> bool {
-        !self.has_mine && !self.revealed
+    fn join(address: &str) -> std::io::Result<Game> {
+        let (client, field) = Client::connect(address)?;
+        let mut game = Game::new(field);
+        game.remote = Some(client);
+        Ok(game)
     }
 
-    fn reveal<F, G>(&mut self, flagged: F, revealed_mine: G)
-    where
-        F: FnOnce(),
-        G: FnOnce()
-    {
-        self.revealed = true;
-        if self.flagged {
-            flagged();
-            self.flagged = false;
+    fn restart(&mut self) {
+        if let Some(remote) = &mut self.remote {
+            remote.send(ClientMessage::NewGame);
+            return;
         }
-        if self.has_mine {
-            revealed_mine();
+        if self.endless.is_some() {
+            self.start_endless(self.endless_seed(), self.field.rules);
+            return;
         }
+        let field = Field::new(self.field.width, self.field.height, self.field.mines, self.field.rules);
+        self.reset(field);
     }
-}
-
-#[derive(Clone, Copy)]
-struct Field {
-    cells: [Cell; NUMBER_OF_CELLS],
-    flags_left: u8,
-}
 
-impl Field {
-    fn new() -> Field {
-        let mut cells = [Cell::new(); NUMBER_OF_CELLS];
-        let mut mines_to_place = 99;
+    fn reset(&mut self, field: Field) {
+        self.scene = Scene::Playing;
+        self.clock = Clock::default();
+        self.game_duration = None;
+        self.was_winner = false;
+        self.should_die = false;
+        self.hit_mines.clear();
+        self.lives = self.config.lives;
+        self.lives_used = 0;
+        self.field = field;
+        self.endless = None;
+        self.current_selection = 0;
+        self.inputs = [None; ACTIONS.len()];
+        self.mouse_press = None;
+        self.animations.skip();
+    }
 
-        // Place Mines
-        while mines_to_place > 0 {
-            for i in 0..cells.len() {
-                if rand::random() {
-                    continue;
-                }
-                if let Some(i_minus) = i.checked_sub(1) {
-                    if cells[i_minus].has_mine {
-                        continue;
-                    }
-                }
-                if let Some(i_minus) = i.checked_sub(NUMBER_OF_ROWS_AND_COLUMNS) {
-                    if cells[i_minus].has_mine {
-                        continue;
-                    }
-                }
-                if i + 1 < cells.len() && cells[i + 1].has_mine {
-                    continue;
-                }
-                if i + NUMBER_OF_ROWS_AND_COLUMNS < cells.len() && cells[i + NUMBER_OF_ROWS_AND_COLUMNS].has_mine {
-                    continue;
-                }
-                let cell = &mut cells[i];
-                if rand::random() && !cell.has_mine && mines_to_place > 0 {
-                    cell.has_mine = true;
-                    mines_to_place -= 1;
-                }
+    fn update(&mut self) {
+        self.animations.update();
+        self.update_stick();
+        if !self.scene.is_menu() && self.scene != Scene::Playing {
+            return;
+        }
+        let delay = Duration::from_millis(self.config.repeat_delay.into());
+        // Held left and right would keep changing a menu row's value, so only up and down repeat in menus
+        let menu = self.scene.is_menu();
+        for action in ACTIONS.into_iter().filter(|action| action.is_movement() && (!menu || matches!(action, Action::MoveUp | Action::MoveDown))) {
+            let Some(held) = &mut self.inputs[action as usize] else {
+                continue;
+            };
+            for _ in 0..held.repeats_due(delay, self.config.repeat_rate) {
+                self.move_input(action);
             }
         }
+    }
 
-        // Increase mines_around
-        for cell_index in 0..cells.len() {
-            let (x, y) = one_d_to_two_d(cell_index);
-            let mut cell = cells[cell_index];
-            if !cell.has_mine {
-                let mut number_of_mines = 0;
-                let mut cells_check = Vec::<(usize, usize)>::new();
-
-                // Left Side
-                if let Some(x_value) = x.checked_sub(1) {
-                    // 0 Cell
-                    if let Some(y_value) = y.checked_sub(1) {
-                        cells_check.push((x_value, y_value));
-                    }
-                    // 3 Cell
-                    cells_check.push((x_value, y));
-                    // 6 Cell
-                    if y < 23 {
-                        cells_check.push((x_value, y + 1));
-                    }
-                }
+    fn update_stick(&mut self) {
+        if self.scene.is_menu() {
+            // Only up and down, a step each time a stick is pushed, left and right would change the row's value over and over
+            let steps: i32 = self.controller_states.values_mut().map(|state| state.menu_steps()).sum();
+            let action = if steps < 0 { Action::MoveUp } else { Action::MoveDown };
+            for _ in 0..steps.unsigned_abs() {
+                self.menu_input(action);
+            }
+            return;
+        }
+        let (x, y) = self.controller_states.values_mut().map(|state| state.stick_steps()).fold((0, 0), |(x, y), (step_x, step_y)| (x + step_x, y + step_y));
+        if self.scene == Scene::Playing && (x, y) != (0, 0) {
+            self.move_cursor(x, y);
+        }
+    }
 
-                // Middle
-                // 1 Cell
-                if let Some(y_value) = y.checked_sub(1) {
-                    cells_check.push((x, y_value));
-                }
-                // 7 Cell
-                if y < 23 {
-                    cells_check.push((x, y + 1));
-                }
+    // Moves the cursor by whole cells, wrapping around the edges of the field
+    fn move_cursor(&mut self, x: i32, y: i32) {
+        if self.endless.is_some() {
+            self.move_endless_cursor(x, y);
+            return;
+        }
+        if self.field.rules.shape == Shape::Hex {
+            self.move_hex_cursor(x, y);
+            return;
+        }
+        let (current_x, current_y) = self.field.index_to_x_y(self.current_selection);
+        let new_x = (current_x as i32 + x).rem_euclid(self.field.width as i32) as usize;
+        let new_y = (current_y as i32 + y).rem_euclid(self.field.height as i32) as usize;
+        self.current_selection = self.field.x_y_to_index(new_x, new_y) + self.layer_start();
+    }
 
-                // Right Side
-                if x < 23 {
-                    // 2 Cell
-                    if let Some(y_value) = y.checked_sub(1) {
-                        cells_check.push((x + 1, y_value));
-                    }
-                    // 5 Cell
-                    cells_check.push((x + 1, y));
-                    // 8 Cell
-                    if y < 23 {
-                        cells_check.push((x + 1, y + 1));
-                    }
-                }
-                for (x, y) in cells_check {
-                    if cells[two_d_to_one_d(x, y)].has_mine {
-                        number_of_mines += 1;
-                    }
-                }
-                cell.mines_around = number_of_mines;
+    // Each step up or down goes to a neighbouring hex, to the left or right of it if moving that way too, otherwise keeping to the same column
+    fn move_hex_cursor(&mut self, x: i32, y: i32) {
+        let (current_x, current_y) = self.field.index_to_x_y(self.current_selection);
+        let (mut new_x, mut new_y) = (current_x as i32, current_y as i32);
+        let (width, height) = (self.field.width as i32, self.field.height as i32);
+        // Wrapping by an even number of rows leaves the cursor on a row shifted the same way as the one it stepped towards
+        let wrap = if height > 1 { height - height % 2 } else { 1 };
+        let mut sideways = x;
+        for _ in 0..y.unsigned_abs() {
+            // Odd rows are pushed half a hex right, so their neighbours above and below to the right are a column further along, and on even rows the ones to the left are a column back
+            let odd = new_y % 2 == 1;
+            new_x += match sideways.signum() {
+                1 if odd => 1,
+                -1 if !odd => -1,
+                _ => 0,
+            };
+            sideways -= sideways.signum();
+            new_y += y.signum();
+            if new_y < 0 {
+                new_y += wrap;
+            } else if new_y >= height {
+                new_y -= wrap;
             }
-            cells[cell_index] = cell;
         }
+        new_x = (new_x + sideways).rem_euclid(width);
+        self.current_selection = self.field.x_y_to_index(new_x as usize, new_y as usize) + self.layer_start();
+    }
 
-        Field {
-            cells,
-            flags_left: 99,
-        }
+    // Moves the cursor to the same place on another layer, stopping at the top and bottom
+    fn move_layer(&mut self, layers: i32) {
+        let layer = self.field.layer(self.current_selection) as i32;
+        let new_layer = (layer + layers).clamp(0, self.field.rules.layers as i32 - 1) as usize;
+        self.current_selection = self.current_selection - self.layer_start() + self.field.layer_start(new_layer);
     }
 
-    fn reveal_surrounding_mines_from_index(&mut self, index: usize) {
-        let (x, y) = one_d_to_two_d(index);
-        self.reveal_surrounding_mines_from_x_y(x, y);
+    // First cell of the layer being looked at
+    fn layer_start(&self) -> usize {
+        self.field.layer_start(self.field.layer(self.current_selection))
     }
 
-    fn reveal_surrounding_mines_from_x_y(&mut self, x: usize, y: usize) {
-        let mut cells = Vec::<(usize, usize)>::new();
+    // Cells on the layer being looked at, the only ones drawn or clicked on
+    fn visible_cells(&self) -> Range<usize> {
+        self.layer_start()..self.layer_start() + self.field.width * self.field.height
+    }
 
-        if let Some(x_value) = x.checked_sub(1) {
-            // 0 Cell
-            if let Some(y_value) = y.checked_sub(1) {
-                cells.push((x_value, y_value));
-            }
-            // 3 Cell
-            cells.push((x_value, y));
-            // 6 Cell
-            if y < 23 {
-                cells.push((x_value, y + 1));
+    // Every key and button ends up here once it has been mapped to an action
+    fn handle_action(&mut self, action: Option<Action>, is_down: bool) {
+        if self.scene == Scene::End {
+            if !is_down {
+                // Select goes back to the menu, anything else plays again
+                self.leave_end(action == Some(Action::Select));
             }
+            return;
         }
+        if let Some(action) = action {
+            self.update_input(is_down, action);
+        }
+    }
 
-        // 1 Cell
-        if let Some(y_value) = y.checked_sub(1) {
-            cells.push((x, y_value));
+    fn update_input(&mut self, is_down: bool, action: Action) {
+        let input = action as usize;
+        if is_down {
+            if self.inputs[input].is_none() {
+                self.inputs[input] = Some(HeldInput::new());
+                if action.is_movement() {
+                    self.move_input(action);
+                }
+            }
+            return;
         }
-        // 7 Cell
-        if y < 23 {
-            cells.push((x, y + 1));
+        // Releases only count for presses made in this scene
+        if self.inputs[input].take().is_none() || action.is_movement() {
+            return;
+        }
+        // Music and mute work the same everywhere
+        match action {
+            Action::Mute => self.toggle_mute(),
+            Action::PlayPause => self.toggle_music(),
+            Action::NextTrack => self.skip_track = true,
+            _ => {},
         }
+        if matches!(action, Action::Mute | Action::PlayPause | Action::NextTrack) {
+            return;
+        }
+        if self.scene.is_menu() {
+            self.menu_input(action);
+            return;
+        }
+        match action {
+            Action::Reveal => self.reveal_from_index(self.current_selection),
+            Action::Flag => self.flag_from_index(self.current_selection),
+            Action::Chord => self.chord_from_index(self.current_selection),
+            Action::Pause => self.pause(),
+            _ => {},
+        }
+    }
 
-        if x < 23 {
-            // 2 Cell
-            if let Some(y_value) = y.checked_sub(1) {
-                cells.push((x + 1, y_value));
-            }
-            // 5 Cell
-            cells.push((x + 1, y));
-            // 8 Cell
-            if y < 23 {
-                cells.push((x + 1, y + 1));
-            }
+    fn move_input(&mut self, action: Action) {
+        if self.scene.is_menu() {
+            self.menu_input(action);
+            return;
         }
-        for (x, y) in cells {
-            let cell = &mut self.cells[two_d_to_one_d(x, y)];
-            if cell.should_reveal() && !cell.has_mine {
-                cell.reveal(|| self.flags_left += 1, || {});
-                if cell.mines_around == 0 {
-                    self.reveal_surrounding_mines_from_x_y(x, y);
-                }
-            }
+        match action {
+            Action::MoveRight => self.move_cursor(1, 0),
+            Action::MoveLeft => self.move_cursor(-1, 0),
+            Action::MoveUp => self.move_cursor(0, -1),
+            Action::MoveDown => self.move_cursor(0, 1),
+            // The shoulders change layers on 3D boards
+            Action::JumpLeft if self.field.rules.layers > 1 => self.move_layer(-1),
+            Action::JumpRight if self.field.rules.layers > 1 => self.move_layer(1),
+            Action::JumpLeft => self.move_cursor(-JUMP_DISTANCE, 0),
+            Action::JumpRight => self.move_cursor(JUMP_DISTANCE, 0),
+            Action::LayerUp => self.move_layer(-1),
+            Action::LayerDown => self.move_layer(1),
+            _ => {},
         }
     }
-}
 
-#[derive(Clone, Copy)]
-struct Game {
-    scene: usize,
-    was_winner: bool,
-    should_die: bool,
-    game_instant: Option<Instant>,
-    game_duration: Option<Duration>,
-    field: Field,
-    current_selection: usize,
-    inputs: [(bool, u8); 8],
-}
+    fn cell_at(&self, x: i32, y: i32) -> Option<usize> {
+        if self.field.rules.shape == Shape::Hex {
+            return self.hex_at(x, y);
+        }
+        let cell_size = self.cell_size as i32;
+        let x = (x - board_left(&self.field, self.cell_size)).div_euclid(cell_size);
+        let y = (y - board_top(&self.field, self.cell_size)).div_euclid(cell_size);
+        let (width, height) = (self.field.width as i32, self.field.height as i32);
+        // The ghost cells around a torus stand for the cells on the other side
+        if self.field.rules.shape == Shape::Torus && (-1..=width).contains(&x) && (-1..=height).contains(&y) {
+            return Some(self.field.x_y_to_index(x.rem_euclid(width) as usize, y.rem_euclid(height) as usize));
+        }
+        if x < 0 || y < 0 || x >= width || y >= height {
+            return None;
+        }
+        Some(self.field.x_y_to_index(x as usize, y as usize) + self.layer_start())
+    }
 
-impl Game {
-    fn new() -> Game {
-        Game {
-            scene: 0,
-            was_winner: false,
-            should_die: false,
-            game_instant: None,
-            game_duration: None,
-            field: Field::new(),
-            current_selection: 0,
-            inputs: [(false, 0); 8],
+    // The hex with the closest centre, only the closest one in each of the nearest three rows can be it
+    fn hex_at(&self, x: i32, y: i32) -> Option<usize> {
+        let (cell_size, cell_height, row_spacing) = (self.cell_size as i32, cell_height(&self.field, self.cell_size) as i32, row_spacing(&self.field, self.cell_size) as i32);
+        let (x, y) = (x - board_left(&self.field, self.cell_size), y - board_top(&self.field, self.cell_size));
+        let row = y.div_euclid(row_spacing);
+        let (column, row) = (row - 1..=row + 1)
+            .map(|row| ((x - row.rem_euclid(2) * cell_size / 2).div_euclid(cell_size), row))
+            .min_by_key(|(column, row)| {
+                let (centre_x, centre_y) = (column * cell_size + row.rem_euclid(2) * cell_size / 2 + cell_size / 2, row * row_spacing + cell_height / 2);
+                (x - centre_x).pow(2) + (y - centre_y).pow(2)
+            })?;
+        if column < 0 || row < 0 || column >= self.field.width as i32 || row >= self.field.height as i32 {
+            return None;
         }
+        Some(self.field.x_y_to_index(column as usize, row as usize))
     }
 
-    fn update(&mut self) {
-        for (i, (is_down, count)) in self.inputs[0..4].iter_mut().enumerate() {
-            if *is_down {
-                if *count == 0 {
-                    *count = 15;
-                    match i {
-                        0 => {
-                            let new_selction = self.current_selection + 1;
-                            self.current_selection = if new_selction % NUMBER_OF_ROWS_AND_COLUMNS == 0 {
-                                new_selction - NUMBER_OF_ROWS_AND_COLUMNS
-                            } else {
-                                new_selction
-                            }
-                        },
-                        1 => {
-                            self.current_selection = if self.current_selection % NUMBER_OF_ROWS_AND_COLUMNS == 0 {
-                                self.current_selection + NUMBER_OF_ROWS_AND_COLUMNS - 1
-                            } else {
-                                self.current_selection - 1
-                            }
-                        },
-                        2 => {
-                            self.current_selection = match self.current_selection.checked_sub(NUMBER_OF_ROWS_AND_COLUMNS) {
-                                Some(t) => t,
-                                None => (self.current_selection % NUMBER_OF_ROWS_AND_COLUMNS) + (NUMBER_OF_CELLS - NUMBER_OF_ROWS_AND_COLUMNS),
-                            };
-                        },
-                        3 => {
-                            self.current_selection = if (self.current_selection + NUMBER_OF_ROWS_AND_COLUMNS) > NUMBER_OF_CELLS {
-                                self.current_selection % NUMBER_OF_ROWS_AND_COLUMNS
-                            } else {
-                                self.current_selection + NUMBER_OF_ROWS_AND_COLUMNS
-                            }
-                        },
-                        _ => {},
-                    }
-                } else {
-                    *count -= 1;
-                }
+    fn mouse_down(&mut self, button: MouseButton, x: i32, y: i32) {
+        self.mouse_press = self.cell_at(x, y).map(|index| MousePress { button, index, inside: true });
+    }
+
+    fn mouse_motion(&mut self, x: i32, y: i32) {
+        let cell = self.cell_at(x, y);
+        if let Some(cell) = cell {
+            // Letting go would act on a different cell than the one pressed
+            if self.hover_endless(cell) {
+                self.mouse_press = None;
             }
+            self.current_selection = cell;
+        }
+        if let Some(press) = &mut self.mouse_press {
+            press.inside = cell == Some(press.index);
         }
     }
 
-    fn update_input(&mut self, is_down: bool, input: usize) {
-        /*
-            2 == UP
-            1 == LEFT
-            3 == DOWN
-            0 == RIGHT
-            5 == B
-            4 == A
-            6 == SELECT
-            7 == START
-        */
-        let mut cause_event = false;
-        if is_down {
-            self.inputs[input] = (true, 15);
-        } else if self.inputs[input].0 {
-            cause_event = true;
-            self.inputs[input] = (false, 0);
-        }
-        if !cause_event {
+    // Releasing anywhere but the cell that was pressed cancels
+    fn mouse_up(&mut self, button: MouseButton, x: i32, y: i32) {
+        let Some(press) = self.mouse_press.take() else {
+            return;
+        };
+        if press.button != button || self.cell_at(x, y) != Some(press.index) {
             return;
         }
-        match input {
-            0 => {
-                let new_selction = self.current_selection + 1;
-                self.current_selection = if new_selction % NUMBER_OF_ROWS_AND_COLUMNS == 0 {
-                    new_selction - NUMBER_OF_ROWS_AND_COLUMNS
-                } else {
-                    new_selction
-                }
-            },
-            1 => {
-                self.current_selection = if self.current_selection % NUMBER_OF_ROWS_AND_COLUMNS == 0 {
-                    self.current_selection + NUMBER_OF_ROWS_AND_COLUMNS - 1
-                } else {
-                    self.current_selection - 1
-                }
-            },
-            2 => {
-                self.current_selection = match self.current_selection.checked_sub(NUMBER_OF_ROWS_AND_COLUMNS) {
-                    Some(t) => t,
-                    None => (self.current_selection % NUMBER_OF_ROWS_AND_COLUMNS) + (NUMBER_OF_CELLS - NUMBER_OF_ROWS_AND_COLUMNS),
-                };
-            },
-            3 => {
-                self.current_selection = if (self.current_selection + NUMBER_OF_ROWS_AND_COLUMNS) > NUMBER_OF_CELLS {
-                    self.current_selection % NUMBER_OF_ROWS_AND_COLUMNS
-                } else {
-                    self.current_selection + NUMBER_OF_ROWS_AND_COLUMNS
-                }
-            },
-            4 => {
-                if !is_down {
-                    // Reveal Cell
-                    self.reveal_from_index(self.current_selection);
-                }
-            }
-            5 => {
-                if !is_down {
-                    // Flag Cell
-                    self.flag_from_index(self.current_selection);
-                }
-            }
+        match button {
+            MouseButton::Left => self.reveal_from_index(press.index),
+            MouseButton::Right => self.flag_from_index(press.index),
+            MouseButton::Middle => self.chord_from_index(press.index),
             _ => {},
         }
     }
 
+    // Hidden cells drawn pushed in while a reveal or chord is held over them
+    fn pressed_cells(&self) -> Vec<usize> {
+        let Some(press) = self.mouse_press.filter(|press| press.inside) else {
+            return Vec::new();
+        };
+        let mut cells = match press.button {
+            MouseButton::Left => vec![press.index],
+            MouseButton::Middle => {
+                let mut cells = self.field.surrounding(press.index);
+                cells.push(press.index);
+                cells
+            },
+            _ => Vec::new(),
+        };
+        cells.retain(|index| !self.field.cells[*index].revealed && !self.field.cells[*index].flagged());
+        cells
+    }
+
     fn reveal_from_index(&mut self, index: usize) {
-        if self.game_instant.is_none() {
-            self.game_instant = Some(Instant::now())
+        if let Some(remote) = &mut self.remote {
+            remote.send(ClientMessage::Reveal(index as u32));
+            return;
+        }
+        if self.endless.is_some() {
+            self.reveal_endless(index);
+            return;
+        }
+        self.clock.start();
+        if self.config.first_click_safe && self.field.cells.iter().all(|cell| !cell.revealed) {
+            self.field.move_mine_from(index);
         }
         let cell = &mut self.field.cells[index];
         if !cell.revealed {
-            if !cell.has_mine {
+            if !cell.has_mine() {
                 cell.revealed = true;
-                if cell.flagged {
-                    self.field.flags_left += 1;
-                    cell.flagged = false;
+                cell.questioned = false;
+                if cell.flagged() {
+                    self.field.flags_left += u32::from(cell.flags);
+                    cell.flags = 0;
                 }
                 if cell.mines_around == 0 {
-                    self.field.reveal_surrounding_mines_from_index(index);
+                    let newly_revealed = self.field.reveal_surrounding_mines_from_index(index);
+                    let revealed = newly_revealed.len();
+                    if self.config.animations {
+                        self.animations.cascade(&self.field, index, &newly_revealed);
+                    }
+                    self.sounds.push(if revealed >= CASCADE_SIZE { Sound::Cascade } else { Sound::Reveal(0) });
+                } else {
+                    self.sounds.push(Sound::Reveal(cell.mines_around));
                 }
-            } else {
+            } else if !self.lose_life(index) {
                 self.should_die = true;
+                self.hit_mines.push(index);
+                if self.config.animations {
+                    self.animations.explode(&self.field, index);
+                }
+                self.sounds.push(Sound::Explosion);
+            }
+        }
+    }
+
+    // The game carries on with time added, returns false when there was no life left to lose
+    fn lose_life(&mut self, index: usize) -> bool {
+        let Some(cleared) = self.field.explode(index, &mut self.lives) else {
+            return false;
+        };
+        self.lives_used += 1;
+        self.clock.add_penalty(LIFE_PENALTY);
+        if self.config.animations {
+            self.animations.hit(index);
+        }
+        self.sounds.push(Sound::Explosion);
+        if cleared {
+            self.should_die = true;
+            self.was_winner = true;
+        }
+        true
+    }
+
+    // Reveals the hidden neighbours of a number once it has as many flags around it
+    fn chord_from_index(&mut self, index: usize) {
+        if let Some(remote) = &mut self.remote {
+            remote.send(ClientMessage::Chord(index as u32));
+            return;
+        }
+        if self.endless.is_some() {
+            self.chord_endless(index);
+            return;
+        }
+        let cell = self.field.cells[index];
+        if !cell.revealed || cell.mines_around == 0 {
+            return;
+        }
+        let neighbours = self.field.surrounding(index);
+        // Mines that went off without ending the game count as flagged
+        let flags_around: u8 = neighbours.iter().map(|neighbour| if self.field.cells[*neighbour].revealed { self.field.cells[*neighbour].mines } else { self.field.cells[*neighbour].flags }).sum();
+        if flags_around != cell.mines_around {
+            return;
+        }
+        for neighbour in neighbours {
+            let neighbour_cell = self.field.cells[neighbour];
+            if !neighbour_cell.revealed && !neighbour_cell.flagged() {
+                self.reveal_from_index(neighbour);
             }
         }
     }
 
     fn flag_from_index(&mut self, index: usize) {
-        if self.game_instant.is_none() {
-            self.game_instant = Some(Instant::now())
+        if let Some(remote) = &mut self.remote {
+            remote.send(ClientMessage::Flag(index as u32));
+            return;
         }
-        let cell = &mut self.field.cells[index];
-        if !cell.revealed {
-            if cell.flagged {
-                self.field.flags_left += 1;
-                cell.flagged = false;
-            } else if self.field.flags_left > 0 {
-                self.field.flags_left -= 1;
-                cell.flagged = true;
-                if self.field.flags_left == 0 {
-                    for cell in self.field.cells {
-                        if cell.has_mine && !cell.flagged {
-                            return;
-                        }
-                    }
+        if self.endless.is_some() {
+            self.flag_endless(index);
+            return;
+        }
+        self.clock.start();
+        match self.field.cycle_flag(index, self.config.question_marks) {
+            Some(true) => {
+                if self.config.animations {
+                    self.animations.flag(index);
+                }
+                self.sounds.push(Sound::Flag);
+                if self.field.is_cleared() {
                     self.should_die = true;
                     self.was_winner = true;
                 }
-            }
+            },
+            Some(false) => self.sounds.push(Sound::Unflag),
+            None => {},
+        }
+    }
+
+    fn finish(&mut self) {
+        self.set_scene(Scene::End);
+        self.should_die = false;
+        if self.clock.is_started() {
+            self.game_duration = Some(self.clock.elapsed());
+        }
+        // Endless boards are never won, so there's nothing to record
+        if self.remote.is_none() && self.endless.is_none() {
+            self.statistics.record(self.difficulty, self.was_winner, self.game_duration);
+            self.statistics.save();
+        }
+        if self.was_winner {
+            self.sounds.push(Sound::Victory);
+        }
+    }
+
+    fn leave_end(&mut self, to_menu: bool) {
+        // The first press only skips to the end screen
+        if self.animations.is_exploding() {
+            self.animations.skip();
+            return;
+        }
+        if to_menu && self.remote.is_none() {
+            self.set_scene(Scene::Menu);
+        } else {
+            self.restart();
+        }
+    }
+
+    fn window_size(&self) -> (u32, u32) {
+        // The pause menu covers the board without resizing the window
+        if self.scene.is_menu() && self.scene != Scene::Paused {
+            (MENU_WIDTH, MENU_HEIGHT)
+        } else {
+            (window_width(&self.field, self.cell_size).max(MIN_WIDTH), window_height(&self.field, self.cell_size).max(MIN_HEIGHT))
+        }
+    }
+
+    // Applies whatever the server has sent since the last frame
+    fn update_remote(&mut self) {
+        let messages = match &mut self.remote {
+            Some(remote) => {
+                remote.send_cursor(self.current_selection);
+                remote.poll()
+            },
+            None => return,
+        };
+        let messages = match messages {
+            Ok(messages) => messages,
+            Err(e) => {
+                eprintln!("{}", e);
+                self.remote = None;
+                self.should_die = true;
+                return;
+            },
+        };
+        for message in messages {
+            match message {
+                ServerMessage::Welcome { .. } => {},
+                ServerMessage::Reset { width, height, mines } => {
+                    self.reset(Field::empty(width as usize, height as usize, mines));
+                    let cells = self.field.cells.len();
+                    if let Some(remote) = &mut self.remote {
+                        remote.cursors.retain(|(_, index)| *index < cells);
+                    }
+                },
+                ServerMessage::Cells(cells) => {
+                    self.clock.start();
+                    // The loudest thing that happened in the update
+                    let revealed = cells.iter().filter(|(_, state)| matches!(state, CellState::Revealed(_))).count();
+                    let highest_revealed = cells.iter().filter_map(|(_, state)| if let CellState::Revealed(mines_around) = state { Some(*mines_around) } else { None }).max();
+                    let sound = if cells.iter().any(|(_, state)| *state == CellState::Exploded) {
+                        Some(Sound::Explosion)
+                    } else if revealed >= CASCADE_SIZE {
+                        Some(Sound::Cascade)
+                    } else if let Some(mines_around) = highest_revealed {
+                        Some(Sound::Reveal(mines_around))
+                    } else if cells.iter().any(|(_, state)| *state == CellState::Flagged) {
+                        Some(Sound::Flag)
+                    } else if !cells.is_empty() {
+                        Some(Sound::Unflag)
+                    } else {
+                        None
+                    };
+                    self.sounds.extend(sound);
+                    for (index, state) in cells {
+                        if let Some(cell) = self.field.cells.get_mut(index as usize) {
+                            state.apply(cell);
+                        }
+                    }
+                },
+                ServerMessage::Status { flags_left, lives } => {
+                    self.field.flags_left = flags_left;
+                    self.lives = lives;
+                },
+                ServerMessage::Cursor { player_id, index } => {
+                    if index as usize >= self.field.cells.len() {
+                        continue;
+                    }
+                    if let Some(remote) = &mut self.remote {
+                        match remote.cursors.iter_mut().find(|(id, _)| *id == player_id) {
+                            Some(cursor) => cursor.1 = index as usize,
+                            None => remote.cursors.push((player_id, index as usize)),
+                        }
+                    }
+                },
+                ServerMessage::Left { player_id } => {
+                    if let Some(remote) = &mut self.remote {
+                        remote.cursors.retain(|(id, _)| *id != player_id);
+                    }
+                },
+                ServerMessage::GameOver { was_winner } => {
+                    self.should_die = true;
+                    self.was_winner = was_winner;
+                },
             }
         }
+    }
+}
+
+// Room around a torus for a faded copy of the cells on the far side
+fn ghost_margin(field: &Field, cell_size: u32) -> u32 {
+    if field.rules.shape == Shape::Torus { cell_size } else { 0 }
+}
+
+// Hexes are cell_size across their flat sides, a bit taller from point to point
+fn cell_height(field: &Field, cell_size: u32) -> u32 {
+    if field.rules.shape == Shape::Hex { (cell_size as f32 * 2.0 / 3f32.sqrt()).round() as u32 } else { cell_size }
+}
+
+// Rows of hexes tuck into each other by a quarter of a hex
+fn row_spacing(field: &Field, cell_size: u32) -> u32 {
+    if field.rules.shape == Shape::Hex { cell_height(field, cell_size) * 3 / 4 } else { cell_size }
+}
+
+fn window_width(field: &Field, cell_size: u32) -> u32 {
+    // Odd rows of hexes stick out half a cell
+    let shift = if field.rules.shape == Shape::Hex { cell_size / 2 } else { 0 };
+    field.width as u32 * cell_size + shift + 2 * ghost_margin(field, cell_size)
+}
+
+fn window_height(field: &Field, cell_size: u32) -> u32 {
+    HEIGHT_PLAY_AREA_START as u32 + (field.height as u32 - 1) * row_spacing(field, cell_size) + cell_height(field, cell_size) + 2 * ghost_margin(field, cell_size)
+}
+
+// Boards narrower than MIN_WIDTH are centred
+fn board_left(field: &Field, cell_size: u32) -> i32 {
+    ((window_width(field, cell_size).max(MIN_WIDTH) - window_width(field, cell_size)) / 2 + ghost_margin(field, cell_size)) as i32
+}
+
+fn board_top(field: &Field, cell_size: u32) -> i32 {
+    (HEIGHT_PLAY_AREA_START as u32 + ghost_margin(field, cell_size)) as i32
+}
+
+enum Mode {
+    Local,
+    // Dedicated server without a window
+    Serve(String),
+    // Server on a background thread that this window also joins
+    Host(String),
+    Join(String),
+    // Times the flood fill on big boards and exits
+    Benchmark,
+}
+
+struct Arguments {
+    mode: Mode,
+    width: Option<usize>,
+    height: Option<usize>,
+    mines: Option<u32>,
+    rules: Rules,
+    lives: u32,
+    endless: bool,
+    // Makes the same endless board every time
+    seed: Option<u64>,
+}
+
+fn parse_arguments() -> Arguments {
+    let mut arguments = Arguments {
+        mode: Mode::Local,
+        width: None,
+        height: None,
+        mines: None,
+        rules: Rules { layers: DEFAULT_LAYERS, ..Rules::default() },
+        lives: 0,
+        endless: false,
+        seed: None,
+    };
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--serve" => arguments.mode = Mode::Serve(args.next().expect("Couldn't find address after --serve")),
+            "--host" => arguments.mode = Mode::Host(args.next().expect("Couldn't find address after --host")),
+            "--join" => arguments.mode = Mode::Join(args.next().expect("Couldn't find address after --join")),
+            "--benchmark" => arguments.mode = Mode::Benchmark,
+            "--size" => {
+                let size = args.next().expect("Couldn't find WIDTHxHEIGHT after --size");
+                let (width, height) = size.split_once('x').expect("Couldn't split --size into WIDTHxHEIGHT");
+                arguments.width = Some(width.parse::<usize>().expect("Couldn't parse --size width").max(2));
+                arguments.height = Some(height.parse::<usize>().expect("Couldn't parse --size height").max(2));
+            },
+            "--mines" => arguments.mines = Some(args.next().and_then(|mines| mines.parse().ok()).expect("Couldn't parse --mines")),
+            "--shape" => {
+                let name = args.next().expect("Couldn't find shape after --shape");
+                arguments.rules.shape = *SHAPES.iter().find(|shape| shape.name().eq_ignore_ascii_case(&name)).expect("Couldn't find a shape with that name");
+            },
+            "--layers" => arguments.rules.layers = args.next().and_then(|layers| layers.parse().ok()).expect("Couldn't parse --layers"),
+            "--neighbourhood" => {
+                let name = args.next().expect("Couldn't find neighbourhood after --neighbourhood");
+                arguments.rules.neighbourhood = *NEIGHBOURHOODS.iter().find(|neighbourhood| neighbourhood.name().eq_ignore_ascii_case(&name)).expect("Couldn't find a neighbourhood with that name");
+            },
+            "--mines-per-cell" => arguments.rules.mines_per_cell = args.next().and_then(|mines| mines.parse().ok()).expect("Couldn't parse --mines-per-cell"),
+            "--endless" => arguments.endless = true,
+            "--seed" => arguments.seed = Some(args.next().and_then(|seed| seed.parse().ok()).expect("Couldn't parse --seed")),
+            "--lives" => arguments.lives = args.next().and_then(|lives| lives.parse().ok()).expect("Couldn't parse --lives"),
+            _ => eprintln!("Unknown argument {}", arg),
+        }
+    }
+    arguments
 }
 
 fn main() {
+    let arguments = parse_arguments();
+    let multiplayer_width = arguments.width.unwrap_or(MULTIPLAYER_COLUMNS);
+    let multiplayer_height = arguments.height.unwrap_or(MULTIPLAYER_ROWS);
+    let server_options = ServerOptions {
+        width: multiplayer_width,
+        height: multiplayer_height,
+        mines: arguments.mines.unwrap_or(MULTIPLAYER_MINES),
+        lives: arguments.lives,
+    };
+
+    let mut game = match arguments.mode {
+        Mode::Local => {
+            let width = arguments.width.unwrap_or(NUMBER_OF_ROWS_AND_COLUMNS);
+            let height = arguments.height.unwrap_or(NUMBER_OF_ROWS_AND_COLUMNS);
+            let mines = arguments.mines.unwrap_or(NUMBER_OF_MINES).min(Field::max_mines(width, height));
+            let mut game = Game::new(Field::new(width, height, mines, arguments.rules));
+            // A board given on the command line skips the menu
+            if arguments.width.is_none() && arguments.mines.is_none() && !arguments.endless {
+                game.set_scene(Scene::Menu);
+            }
+            game
+        },
+        Mode::Benchmark => {
+            field::benchmark();
+            return;
+        },
+        Mode::Serve(address) => {
+            net::serve(&address, server_options).expect("Couldn't start server");
+            return;
+        },
+        Mode::Host(address) => {
+            let server_address = net::spawn_server(&address, server_options).expect("Couldn't start server");
+            Game::join(&SocketAddr::from(([127, 0, 0, 1], server_address.port())).to_string()).expect("Couldn't join own server")
+        },
+        Mode::Join(address) => Game::join(&address).expect("Couldn't join server"),
+    };
+    game.load_config(Config::load());
+    game.statistics = Statistics::load();
+    // A board given on the command line was made before there was a config to take the lives from
+    if game.remote.is_none() {
+        game.lives = game.config.lives;
+    }
+    if arguments.endless && game.remote.is_none() {
+        game.endless_seed = arguments.seed;
+        game.start_endless(game.endless_seed(), arguments.rules);
+    }
+
     let sdl_context = sdl2::init().expect("Couldn't init sdl");
     let video_subsystem = sdl_context.video().expect("Couldn't init sdl video");
-    let _audio_subsystem = sdl_context.audio().expect("Couldn't init sdl audio");
+    let audio_subsystem = sdl_context.audio().expect("Couldn't init sdl audio");
     let game_controller_subsystem = sdl_context.game_controller().expect("Couldn't init sdl game_controller");
     let ttf_context = sdl2::ttf::init().map_err(|e| e.to_string()).expect("Couldn't init ttf");
 
-    let window = video_subsystem.window("Minesweeper", WIDTH.into(), HEIGHT.into())
+    let (width, height) = game.window_size();
+    let window = video_subsystem.window("Minesweeper", width * game.config.window_scale, height * game.config.window_scale)
         .position_centered()
+        .resizable()
+        .allow_highdpi()
         .build()
         .expect("Couldn't create window from video");
 
@@ -459,41 +877,38 @@ fn main() {
 
     let texture_creator = canvas.texture_creator();
 
-    let debug_mine_texture = texture_creator.load_texture_bytes(DEBUG_MINE).expect("Couldn't create texture from DEBUG_MINE");
-    let flagged_mine_texture = texture_creator.load_texture_bytes(FLAGGED_MINE).expect("Couldn't create texture from FLAGGED_MINE");
-    let unflagged_mine_texture = texture_creator.load_texture_bytes(UNFLAGGED_MINE).expect("Couldn't create texture from UNFLAGGED_MINE");
-    let revealed_mine_texture = texture_creator.load_texture_bytes(REVEALED_MINE).expect("Couldn't create texture from REVEALED_MINE");
-    let cursor_texture = texture_creator.load_texture_bytes(CURSOR).expect("Couldn't create texture from CURSOR");
-
-    let revealed_mine_1_texture = texture_creator.load_texture_bytes(REVEALED_MINE_1).expect("Couldn't create texture from REVEALED_MINE_1");
-    let revealed_mine_2_texture = texture_creator.load_texture_bytes(REVEALED_MINE_2).expect("Couldn't create texture from REVEALED_MINE_2");
-    let revealed_mine_3_texture = texture_creator.load_texture_bytes(REVEALED_MINE_3).expect("Couldn't create texture from REVEALED_MINE_3");
-    let revealed_mine_4_texture = texture_creator.load_texture_bytes(REVEALED_MINE_4).expect("Couldn't create texture from REVEALED_MINE_4");
-    let revealed_mine_5_texture = texture_creator.load_texture_bytes(REVEALED_MINE_5).expect("Couldn't create texture from REVEALED_MINE_5");
-    let revealed_mine_6_texture = texture_creator.load_texture_bytes(REVEALED_MINE_6).expect("Couldn't create texture from REVEALED_MINE_6");
-    let revealed_mine_7_texture = texture_creator.load_texture_bytes(REVEALED_MINE_7).expect("Couldn't create texture from REVEALED_MINE_7");
-    let revealed_mine_8_texture = texture_creator.load_texture_bytes(REVEALED_MINE_8).expect("Couldn't create texture from REVEALED_MINE_8");
-
-    let textures = vec![debug_mine_texture, flagged_mine_texture, unflagged_mine_texture, revealed_mine_texture, cursor_texture, revealed_mine_1_texture, revealed_mine_2_texture, revealed_mine_3_texture, revealed_mine_4_texture, revealed_mine_5_texture, revealed_mine_6_texture, revealed_mine_7_texture, revealed_mine_8_texture];
+    let mut theme = Theme::load_or_embedded(&texture_creator, &game.config.theme);
+    game.config.theme = theme.name.clone();
+    game.cell_size = theme.tile_size;
 
     let _mixer_content = sdl2::mixer::init(InitFlag::MP3);
     let _ = sdl2::mixer::open_audio(DEFAULT_FREQUENCY, DEFAULT_FORMAT, DEFAULT_CHANNELS, 1_024);
 
-    let music = sdl2::mixer::Music::from_static_bytes(AWAKE10_MEGA_WALL).expect("Couldn't create music from static bytes");
-    let _ = music.play(-1);
+    let sound_effects = SoundEffects::load();
+
+    let mut music_player = MusicPlayer::new(&game.config);
+
+    let desired_spec = AudioSpecDesired {
+        freq: Some(SAMPLE_RATE.into()),
+        channels: Some(1),
+        samples: None,
+    };
+    let audio_queue: Option<AudioQueue<i16>> = audio_subsystem.open_queue(None, &desired_spec).ok();
+    // Sound effects and announcement tones are both synthesized into this queue
+    let mut synth = Synth::new(audio_queue);
+    let mut announcer = Announcer::new();
 
     let number_of_joystics = game_controller_subsystem.num_joysticks().expect("Couldn't find any joysticks");
-    let _controller = (0..number_of_joystics)
-        .find_map(|id| {
-            if !game_controller_subsystem.is_game_controller(id) {
-                return None;
-            }
-            game_controller_subsystem.open(id).ok()
-        });
+    // Every controller drives the same cursor, more can be plugged in while playing
+    let mut controllers: Vec<GameController> = (0..number_of_joystics)
+        .filter(|id| game_controller_subsystem.is_game_controller(*id))
+        .filter_map(|id| game_controller_subsystem.open(id).ok())
+        .collect();
 
     let mut event_pump = sdl_context.event_pump().expect("Couldn't get event_pump from sdl_context");
 
-    let mut game = Game::new();
+    // Logical size and scale the window was last sized for
+    let mut sized_for = (0, 0, 0);
 
     let mut previous_instant: Instant = Instant::now();
     let mut current_instant: Instant;
@@ -502,151 +917,143 @@ fn main() {
 
     'running: loop {
         game.update();
+        game.update_remote();
 
         for event in event_pump.poll_iter() {
             match event {
                 Event::Quit {..} => {
                     break 'running
                 },
+                Event::Window { win_event: WindowEvent::FocusLost, .. } => {
+                    game.pause();
+                },
+                Event::MouseMotion { y, .. } if game.scene.is_menu() => {
+                    game.menu_hover(y);
+                },
+                Event::MouseWheel { y, .. } if game.scene.is_menu() && game.rebinding.is_none() => {
+                    game.menu_input(if y > 0 { Action::MoveUp } else { Action::MoveDown });
+                },
+                Event::MouseMotion { x, y, .. } if game.scene == Scene::Playing => {
+                    game.mouse_motion(x, y);
+                },
+                Event::MouseButtonDown { mouse_btn, x, y, .. } if game.scene == Scene::Playing => {
+                    game.mouse_down(mouse_btn, x, y);
+                },
                 Event::MouseButtonUp { mouse_btn, x, y, .. } => {
-                    if game.scene == 1 {
-                        game.scene = 0;
-                        game.game_instant = None;
-                        game.game_duration = None;
-                        game.was_winner = false;
-                        game.field = Field::new();
-                        game.current_selection = 0;
-                        game.inputs = [(false, 0); 8];
+                    if game.scene == Scene::End {
+                        game.leave_end(false);
                         continue;
                     }
-                    let y = y - HEIGHT_PLAY_AREA_START as i32;
-                    if y >= 0 {
-                        let pixel_to_2d_y = y / 32;
-                        let pixel_to_2d_x = x / 32;
-                        let cell_number = pixel_to_2d_y * (NUMBER_OF_ROWS_AND_COLUMNS as i32) + pixel_to_2d_x;
-                        if let Ok(cell_number) = usize::try_from(cell_number) {
-                            match mouse_btn {
-                                MouseButton::Left => {
-                                    game.reveal_from_index(cell_number);
-                                },
-                                MouseButton::Right => {
-                                    game.flag_from_index(cell_number);
-                                },
-                                _ => {},
-                            }
-                        }
+                    if game.scene.is_menu() {
+                        game.menu_click(y, if mouse_btn == MouseButton::Right { -1 } else { 0 });
+                        continue;
                     }
+                    game.mouse_up(mouse_btn, x, y);
+                },
+                Event::KeyDown { keycode: Some(Keycode::F11), repeat: false, .. } if game.rebinding.is_none() => {
+                    game.toggle_fullscreen();
                 },
                 Event::KeyDown { keycode: Some(key_down), repeat: false, .. } => {
-                    let key_code: i8 = match key_down {
-                        Keycode::W => 2, // UP
-                        Keycode::A => 1, // LEFT
-                        Keycode::S => 3, // DOWN
-                        Keycode::D => 0, // RIGHT
-                        Keycode::H => 5, // B
-                        Keycode::U => 4, // A
-                        Keycode::B => 6, // SELECT
-                        Keycode::N => 7, // START
-                        _ => -1,
-                    };
-                    if let Ok(key_code) = usize::try_from(key_code) {
-                        game.update_input(true, key_code);
+                    if game.rebinding.is_some() {
+                        game.bind_key(key_down.name());
+                        continue;
                     }
+                    game.handle_action(input::key_action(&game.config.key_bindings, key_down), true);
                 },
                 Event::KeyUp { keycode: Some(key_up), repeat: false, .. } => {
-                    if game.scene == 1 {
-                        game.scene = 0;
-                        game.game_instant = None;
-                        game.game_duration = None;
-                        game.was_winner = false;
-                        game.field = Field::new();
-                        game.current_selection = 0;
-                        game.inputs = [(false, 0); 8];
-                        continue;
-                    }
-                    let key_code: i8 = match key_up {
-                        Keycode::W => 2, // UP
-                        Keycode::A => 1, // LEFT
-                        Keycode::S => 3, // DOWN
-                        Keycode::D => 0, // RIGHT
-                        Keycode::H => 5, // B
-                        Keycode::U => 4, // A
-                        Keycode::B => 6, // SELECT
-                        Keycode::N => 7, // START
-                        _ => -1,
-                    };
-                    if let Ok(key_code) = usize::try_from(key_code) {
-                        game.update_input(false, key_code);
-                    }
+                    game.handle_action(input::key_action(&game.config.key_bindings, key_up), false);
                 },
                 Event::ControllerButtonDown { button, .. } => {
-                    let key_code: i8 = match button {
-                        Button::DPadUp => 2, // UP
-                        Button::DPadLeft => 1, // LEFT
-                        Button::DPadDown => 3, // DOWN
-                        Button::DPadRight => 0, // RIGHT
-                        Button::A => 5, // B
-                        Button::B => 4, // A
-                        Button::Back => 6, // SELECT
-                        Button::Start => 7, // START
-                        _ => -1,
-                    };
-                    if let Ok(key_code) = usize::try_from(key_code) {
-                        game.update_input(true, key_code);
+                    if game.rebinding.is_some() {
+                        game.bind_button(button.string());
+                        continue;
                     }
+                    game.handle_action(input::button_action(&game.config.button_bindings, button), true);
                 },
                 Event::ControllerButtonUp { button, .. } => {
-                    if game.scene == 1 {
-                        game.scene = 0;
-                        game.game_instant = None;
-                        game.game_duration = None;
-                        game.was_winner = false;
-                        game.field = Field::new();
-                        game.current_selection = 0;
-                        game.inputs = [(false, 0); 8];
-                        continue;
+                    game.handle_action(input::button_action(&game.config.button_bindings, button), false);
+                },
+                Event::ControllerAxisMotion { which, axis, value, .. } => {
+                    if let Some(is_down) = game.controller_states.entry(which).or_default().axis_motion(axis, value) {
+                        if game.rebinding.is_some() {
+                            if is_down {
+                                game.bind_button(axis.string());
+                            }
+                            continue;
+                        }
+                        game.handle_action(input::axis_action(&game.config.button_bindings, axis), is_down);
                     }
-                    let key_code: i8 = match button {
-                        Button::DPadUp => 2, // UP
-                        Button::DPadLeft => 1, // LEFT
-                        Button::DPadDown => 3, // DOWN
-                        Button::DPadRight => 0, // RIGHT
-                        Button::A => 5, // B
-                        Button::B => 4, // A
-                        Button::Back => 6, // SELECT
-                        Button::Start => 7, // START
-                        _ => -1,
-                    };
-                    if let Ok(key_code) = usize::try_from(key_code) {
-                        game.update_input(false, key_code);
+                },
+                Event::ControllerDeviceAdded { which, .. } => {
+                    // Controllers connected at startup are announced too
+                    if let Ok(controller) = game_controller_subsystem.open(which) {
+                        if controllers.iter().all(|opened| opened.instance_id() != controller.instance_id()) {
+                            controllers.push(controller);
+                        }
                     }
                 },
+                Event::ControllerDeviceRemoved { which, .. } => {
+                    controllers.retain(|controller| controller.instance_id() != which);
+                    // Don't leave the cursor drifting on a stick that's gone
+                    game.controller_states.remove(&which);
+                },
                 _ => (),
             }
         }
 
+        if game.should_quit {
+            break 'running;
+        }
+
         if game.should_die {
-            game.scene = 1;
-            game.should_die = false;
-            if let Some(game_instant) = game.game_instant {
-                game.game_duration = Some(game_instant.elapsed());
+            game.finish();
+        }
+
+        if game.config.theme != theme.name {
+            theme = Theme::load_or_embedded(&texture_creator, &game.config.theme);
+            game.config.theme = theme.name.clone();
+            game.cell_size = theme.tile_size;
+        }
+
+        // The window only snaps back to the scaled game size when the game size or scale changes, otherwise whatever size it was resized to is kept.
+        // Rendering and mouse coordinates stay in logical pixels, SDL letterboxes and maps them to the window and HiDPI displays for us.
+        let (width, height) = game.window_size();
+        if (width, height, game.config.window_scale) != sized_for {
+            sized_for = (width, height, game.config.window_scale);
+            let _ = canvas.set_logical_size(width, height);
+            if !game.config.fullscreen {
+                let _ = canvas.window_mut().set_size(width * game.config.window_scale, height * game.config.window_scale);
+            }
+        }
+        if canvas.integer_scale() != game.config.integer_scaling {
+            let _ = canvas.set_integer_scale(game.config.integer_scaling);
+        }
+        let fullscreen = if game.config.fullscreen { FullscreenType::Desktop } else { FullscreenType::Off };
+        if canvas.window().fullscreen_state() != fullscreen {
+            let _ = canvas.window_mut().set_fullscreen(fullscreen);
+        }
+
+        announcer.announce(game.config.announcements, game.announcement(), &mut synth);
+        music_player.update(&mut game);
+
+        for sound in game.sounds.drain(..) {
+            if game.config.sound_effects && !game.config.muted {
+                sound_effects.play(sound, game.config.sound_volume, &mut synth);
             }
         }
 
         canvas.clear();
-        
-        if game.scene == 0 {
-            render_game(&game, &mut canvas, &textures, &font);
-        } else if game.scene == 1 {
-            render_end(&game, &mut canvas, &font);
+
+        match game.scene {
+            Scene::Playing => render_game(&game, &mut canvas, &theme, &font),
+            // The board stays up until the explosion is over
+            Scene::End if game.animations.is_exploding() => render_game(&game, &mut canvas, &theme, &font),
+            Scene::End => render_end(&game, &mut canvas, &font),
+            Scene::Menu | Scene::CustomBoard | Scene::Statistics | Scene::Settings | Scene::Bindings | Scene::Paused => render_menu(&game, &mut canvas, &font),
         }
-        
 
         canvas.present();
 
-        //let _ = device.queue_audio(&spu.audio_data);
-        //spu.audio_data.clear();
-
         current_instant = Instant::now();
         let elapsed = current_instant - previous_instant;
         previous_instant = current_instant;
@@ -656,46 +1063,165 @@ fn main() {
     }
 }
 
-fn render_game(game: &Game, canvas: &mut Canvas<Window>, textures: &[Texture], font: &Font) {
+fn render_game(game: &Game, canvas: &mut Canvas<Window>, theme: &Theme, font: &Font) {
+    let colors = game.config.palette.colors();
     canvas.set_blend_mode(BlendMode::Blend);
-    canvas.set_draw_color(Color::RGB(128, 128, 128));
-    let _ = canvas.fill_rect(Rect::new(0, 0, WIDTH.into(), HEIGHT_PLAY_AREA_START.into()));
+    canvas.set_draw_color(colors.background);
+    let (width, _) = canvas.logical_size();
+    let _ = canvas.fill_rect(Rect::new(0, 0, width, HEIGHT_PLAY_AREA_START.into()));
     canvas.set_draw_color(Color::RGB(0, 0, 0));
-    for (i, cell) in game.field.cells.iter().enumerate() {
-        let (x, y) = one_d_to_two_d(i);
-        let texture = if cell.revealed {
-            match cell.mines_around {
-                1..=8 => {
-                    &textures[4 + (cell.mines_around as usize)]
-                },
-                _ => {
-                    &textures[3]
-                },
+    let pressed_cells = game.pressed_cells();
+    for i in game.visible_cells() {
+        let tile = cell_tile(game, i, &pressed_cells);
+        if game.field.rules.shape == Shape::Hex {
+            draw_hex_cell(canvas, tile, cell_rect(game, i), game.animations.flag_scale(i), &colors);
+            continue;
+        }
+        match tile {
+            // The palette's numbers are drawn over a blank tile below
+            Tile::Revealed | Tile::Number(_) if colors.numbers.is_some() => {
+                theme.draw(canvas, Tile::Revealed, cell_rect(game, i));
+                if let Some(revealed) = colors.revealed {
+                    canvas.set_draw_color(revealed);
+                    let _ = canvas.fill_rect(cell_rect(game, i));
+                }
+            },
+            // Popping in over the hidden tile
+            Tile::Flagged if game.animations.flag_scale(i) < 1.0 => {
+                theme.draw(canvas, Tile::Hidden, cell_rect(game, i));
+                theme.draw(canvas, Tile::Flagged, scale_rect(cell_rect(game, i), game.animations.flag_scale(i)));
+            },
+            tile => theme.draw(canvas, tile, cell_rect(game, i)),
+        }
+        // The same cell on the layers above and below, small and faded in the corners
+        if game.field.rules.layers > 1 {
+            let rect = cell_rect(game, i);
+            let (preview_width, preview_height) = (rect.width() / 3, rect.height() / 3);
+            let layer = game.field.layer(i);
+            let above = layer.checked_sub(1).map(|above| (above, rect.top_left()));
+            let below = Some(layer + 1).filter(|below| *below < game.field.rules.layers).map(|below| (below, Point::new(rect.right() - preview_width as i32, rect.bottom() - preview_height as i32)));
+            let Color { r, g, b, .. } = colors.background;
+            for (other_layer, corner) in above.into_iter().chain(below) {
+                let preview = Rect::new(corner.x(), corner.y(), preview_width, preview_height);
+                let other = i - game.field.layer_start(layer) + game.field.layer_start(other_layer);
+                theme.draw(canvas, cell_tile(game, other, &[]), preview);
+                canvas.set_draw_color(Color::RGBA(r, g, b, 120));
+                let _ = canvas.fill_rect(preview);
             }
-        } else if cell.flagged {
-            &textures[1]
-        } else {
-            &textures[2]
-        };
-        canvas.copy(texture, None, Some(Rect::new(32 * (x as i32), HEIGHT_PLAY_AREA_START as i32 + (y as i32) * 32, 32, 32))).expect("Couldn't copy canvas");
+        }
+    }
+    if game.field.rules.shape == Shape::Torus {
+        let (width, height) = (game.field.width as i32, game.field.height as i32);
+        let cell_size = game.cell_size as i32;
+        let Color { r, g, b, .. } = colors.background;
+        for y in -1..=height {
+            for x in -1..=width {
+                if (0..width).contains(&x) && (0..height).contains(&y) {
+                    continue;
+                }
+                let index = game.field.x_y_to_index(x.rem_euclid(width) as usize, y.rem_euclid(height) as usize);
+                let rect = Rect::new(board_left(&game.field, game.cell_size) + x * cell_size, board_top(&game.field, game.cell_size) + y * cell_size, game.cell_size, game.cell_size);
+                theme.draw(canvas, cell_tile(game, index, &pressed_cells), rect);
+                canvas.set_draw_color(Color::RGBA(r, g, b, 160));
+                let _ = canvas.fill_rect(rect);
+            }
+        }
+    }
+    if let Some((index, progress)) = game.animations.blast() {
+        // Grows out to the cells around the mine while fading away
+        let blast = scale_rect(cell_rect(game, index), 1.0 + 2.0 * progress);
+        let Color { r, g, b, .. } = colors.highlight;
+        canvas.set_draw_color(Color::RGBA(r, g, b, ((1.0 - progress) * 200.0) as u8));
+        let _ = canvas.fill_rect(blast);
     }
     canvas.set_blend_mode(BlendMode::None);
 
     let texture_creator = canvas.texture_creator();
 
-    let time_surface = font.render(&format!("Time: {}", if let Some(game_instant) = game.game_instant { game_instant.elapsed().as_secs() } else { 0 })).solid(Color::RGB(0, 0, 0)).expect("Couldn't render time font");
+    // Hexes have no number tiles of their own, and no theme has tiles past 8 for 3D boards
+    let number_colors = colors.numbers.or((game.field.rules.shape == Shape::Hex).then_some(NUMBER_COLORS));
+    let drawn_numbers: Vec<usize> = game.visible_cells()
+        .filter(|i| {
+            let cell = game.field.cells[*i];
+            cell.revealed && !cell.has_mine() && !game.animations.is_hidden(*i) && (cell.mines_around > 8 || (number_colors.is_some() && cell.mines_around > 0))
+        })
+        .collect();
+    if let Some(highest) = drawn_numbers.iter().map(|i| game.field.cells[*i].mines_around).max() {
+        let number_textures: Vec<_> = (1..=highest)
+            .map(|number| {
+                let color = number_colors.unwrap_or(NUMBER_COLORS)[(number as usize - 1) % NUMBER_COLORS.len()];
+                let number_surface = font.render(&number.to_string()).solid(color).expect("Couldn't render number font");
+                texture_creator.create_texture_from_surface(number_surface).expect("Could create number texture from font surface")
+            })
+            .collect();
+        for i in drawn_numbers {
+            let number = game.field.cells[i].mines_around;
+            let rect = cell_rect(game, i);
+            let number_width = (rect.width() / 2 * number.to_string().len() as u32).min(rect.width());
+            // Drawn once per pixel of weight, each a pixel further right
+            for offset in 0..palette::number_weight(number) {
+                let number_rect = Rect::new(rect.x() + (rect.width() - number_width) as i32 / 2 + offset - 1, rect.y(), number_width, rect.height());
+                canvas.copy(&number_textures[number as usize - 1], None, Some(number_rect)).expect("Couldn't copy canvas");
+            }
+        }
+    }
+
+    if let Some(outline) = colors.outline {
+        for i in game.visible_cells() {
+            let cell = game.field.cells[i];
+            if cell.flagged() && !cell.revealed {
+                draw_patterned_rect(canvas, cell_rect(game, i), outline, 1);
+            }
+        }
+    }
+
+    // Flags have a single texture, cells with more than one flag get the count drawn in the corner
+    let multiple_flags: Vec<usize> = game.visible_cells().filter(|i| game.field.cells[*i].flags > 1 && !game.field.cells[*i].revealed).collect();
+    if !multiple_flags.is_empty() {
+        let count_textures: Vec<_> = (2..=MAX_MINES_PER_CELL)
+            .map(|count| {
+                let count_surface = font.render(&count.to_string()).solid(Color::RGB(0, 0, 0)).expect("Couldn't render flag count font");
+                texture_creator.create_texture_from_surface(count_surface).expect("Could create flag count texture from font surface")
+            })
+            .collect();
+        for i in multiple_flags {
+            let rect = cell_rect(game, i);
+            let count_rect = Rect::new(rect.x() + rect.width() as i32 * 5 / 8, rect.y() + rect.height() as i32 / 2, rect.width() / 4, rect.height() / 2);
+            canvas.copy(&count_textures[game.field.cells[i].flags as usize - 2], None, Some(count_rect)).expect("Couldn't copy canvas");
+        }
+    }
+
+    // Question marks have no texture of their own, the mark is drawn over the unflagged tile
+    if game.visible_cells().any(|i| game.field.cells[i].questioned && !game.field.cells[i].revealed) {
+        let question_surface = font.render("?").solid(Color::RGB(0, 0, 0)).expect("Couldn't render question font");
+        let question_texture = texture_creator.create_texture_from_surface(question_surface).expect("Could create question texture from font surface");
+        for i in game.visible_cells() {
+            let cell = game.field.cells[i];
+            if cell.questioned && !cell.revealed {
+                let rect = cell_rect(game, i);
+                canvas.copy(&question_texture, None, Some(Rect::new(rect.x() + rect.width() as i32 / 4, rect.y(), rect.width() / 2, rect.height()))).expect("Couldn't copy canvas");
+            }
+        }
+    }
+
+    let time_surface = font.render(&format!("Time: {}", game.clock.elapsed().as_secs())).solid(colors.text).expect("Couldn't render time font");
     let time_texture = texture_creator.create_texture_from_surface(time_surface).expect("Could create time texture from font surface");
 
     const TIME_WIDTH: u32 = 64;
     const TIME_HEIGHT: u32 = 32;
     canvas.copy(&time_texture, None, Some(Rect::new(0, 0, TIME_WIDTH, TIME_HEIGHT))).expect("Couldn't copy canvas");
 
-    let flag_surface = font.render(&format!("Flags: {}", game.field.flags_left)).solid(Color::RGB(0, 0, 0)).expect("Couldn't render flag font");
+    // Endless boards have no mine count to count flags down from, the score goes there instead
+    let flag_text = match &game.endless {
+        Some(endless) => format!("Cleared: {}", endless.cleared),
+        None => format!("Flags: {}", game.field.flags_left),
+    };
+    let flag_surface = font.render(&flag_text).solid(colors.text).expect("Couldn't render flag font");
     let flag_texture = texture_creator.create_texture_from_surface(flag_surface).expect("Could create flag texture from font surface");
 
     const FLAG_WIDTH: u32 = 64;
     const FLAG_HEIGHT: u32 = 32;
-    canvas.copy(&flag_texture, None, Some(Rect::new((WIDTH / 2).into(), 0, FLAG_WIDTH, FLAG_HEIGHT))).expect("Couldn't copy canvas");
+    canvas.copy(&flag_texture, None, Some(Rect::new((width / 2) as i32, 0, FLAG_WIDTH, FLAG_HEIGHT))).expect("Couldn't copy canvas");
 
     let watermark_surface = font.render("Palaster").solid(Color::RGB(255, 0, 0)).expect("Couldn't render watermark font");
     let watermark_texture = texture_creator.create_texture_from_surface(watermark_surface).expect("Could create watermark texture from font surface");
@@ -704,73 +1230,289 @@ fn render_game(game: &Game, canvas: &mut Canvas<Window>, textures: &[Texture], f
     const WATERMARK_HEIGHT: u32 = 32;
     canvas.copy(&watermark_texture, None, Some(Rect::new(0, (HEIGHT_PLAY_AREA_START as u32 - WATERMARK_HEIGHT) as i32, WATERMARK_WIDTH, WATERMARK_HEIGHT))).expect("Couldn't copy canvas");
 
+    if game.field.rules.layers > 1 {
+        let layer_surface = font.render(&format!("Layer: {}/{}", game.field.layer(game.current_selection) + 1, game.field.rules.layers)).solid(colors.text).expect("Couldn't render layer font");
+        let layer_texture = texture_creator.create_texture_from_surface(layer_surface).expect("Could create layer texture from font surface");
+
+        const LAYER_WIDTH: u32 = 64;
+        const LAYER_HEIGHT: u32 = 32;
+        canvas.copy(&layer_texture, None, Some(Rect::new((width / 4 * 3) as i32, 0, LAYER_WIDTH, LAYER_HEIGHT))).expect("Couldn't copy canvas");
+    }
+
+    if game.remote.is_some() || game.config.lives > 0 {
+        let lives_surface = font.render(&format!("Lives: {}", game.lives)).solid(colors.text).expect("Couldn't render lives font");
+        let lives_texture = texture_creator.create_texture_from_surface(lives_surface).expect("Could create lives texture from font surface");
+
+        const LIVES_WIDTH: u32 = 64;
+        const LIVES_HEIGHT: u32 = 32;
+        canvas.copy(&lives_texture, None, Some(Rect::new((width / 4) as i32, 0, LIVES_WIDTH, LIVES_HEIGHT))).expect("Couldn't copy canvas");
+    }
+
+    if let Some(remote) = &game.remote {
+        // Other players' cursors
+        for (player_id, index) in &remote.cursors {
+            let (r, g, b) = PLAYER_COLORS[*player_id as usize % PLAYER_COLORS.len()];
+            canvas.set_draw_color(Color::RGB(r, g, b));
+            let rect = cell_rect(game, *index);
+            let _ = canvas.draw_rect(rect);
+            let _ = canvas.draw_rect(Rect::new(rect.x() + 1, rect.y() + 1, rect.width() - 2, rect.height() - 2));
+        }
+        let (r, g, b) = PLAYER_COLORS[remote.player_id as usize % PLAYER_COLORS.len()];
+        canvas.set_draw_color(Color::RGB(r, g, b));
+        let _ = canvas.draw_rect(cell_rect(game, game.current_selection));
+    }
+
     // Cursor
-    if game.current_selection == 0 {
-        canvas.copy(&textures[4], None, Some(Rect::new(0, HEIGHT_PLAY_AREA_START as i32, 32, 32))).expect("Couldn't copy canvas");
+    if game.field.rules.shape == Shape::Hex {
+        canvas.set_draw_color(colors.highlight);
+        let rect = cell_rect(game, game.current_selection);
+        draw_hex_outline(canvas, rect);
+        draw_hex_outline(canvas, Rect::new(rect.x() + 1, rect.y() + 1, rect.width() - 2, rect.height() - 2));
+    } else {
+        theme.draw(canvas, Tile::Cursor, cell_rect(game, game.current_selection));
+    }
+    if let Some(outline) = colors.outline {
+        draw_patterned_rect(canvas, cell_rect(game, game.current_selection), outline, 3);
+    }
+}
+
+// What a cell is drawn as
+fn cell_tile(game: &Game, i: usize, pressed_cells: &[usize]) -> Tile {
+    let cell = game.field.cells[i];
+    if (cell.revealed && cell.has_mine()) || game.animations.is_mine_shown(i) {
+        // Only exploded mines in multiplayer are revealed, the rest are shown as they go off
+        Tile::Exploded
+    } else if game.animations.is_hidden(i) {
+        Tile::Hidden
+    } else if cell.revealed {
+        match cell.mines_around {
+            1..=8 => {
+                Tile::Number(cell.mines_around)
+            },
+            _ => {
+                Tile::Revealed
+            },
+        }
+    } else if cell.flagged() {
+        Tile::Flagged
+    } else if pressed_cells.contains(&i) {
+        // Pushed in, like an empty revealed cell
+        Tile::Revealed
+    } else {
+        Tile::Hidden
+    }
+}
+
+// Hexes are drawn rather than taken from the theme, pointing up and down and filling the width of the rect
+fn draw_hex_cell(canvas: &mut Canvas<Window>, tile: Tile, rect: Rect, flag_scale: f32, colors: &Colors) {
+    let hidden = Color::RGB(170, 170, 170);
+    let fill = match tile {
+        Tile::Revealed | Tile::Number(_) => colors.revealed.unwrap_or(Color::RGB(220, 220, 220)),
+        Tile::Exploded => Color::RGB(220, 40, 40),
+        _ => hidden,
+    };
+    canvas.set_draw_color(fill);
+    fill_hex(canvas, rect);
+    canvas.set_draw_color(Color::RGB(90, 90, 90));
+    draw_hex_outline(canvas, rect);
+    match tile {
+        // A small black hex for the mine
+        Tile::Exploded => {
+            canvas.set_draw_color(Color::RGB(0, 0, 0));
+            fill_hex(canvas, scale_rect(rect, 0.4));
+        },
+        // A pole with a pennant on it
+        Tile::Flagged => {
+            let flag = scale_rect(rect, 0.5 * flag_scale);
+            let pole = flag.x() + flag.width() as i32 / 3;
+            canvas.set_draw_color(Color::RGB(0, 0, 0));
+            let _ = canvas.draw_line((pole, flag.top()), (pole, flag.bottom()));
+            canvas.set_draw_color(Color::RGB(220, 0, 0));
+            let pennant = flag.height() as i32 / 2;
+            for y in 0..pennant {
+                let length = (flag.right() - pole) * (pennant - (2 * y - pennant).abs()) / pennant;
+                let _ = canvas.draw_line((pole + 1, flag.top() + y), (pole + length, flag.top() + y));
+            }
+        },
+        _ => {},
+    }
+}
+
+// Width of a hex at a row of pixels from its top
+fn hex_half_width(rect: Rect, y: i32) -> i32 {
+    let quarter = rect.height() as i32 / 4;
+    let half_width = rect.width() as i32 / 2;
+    if y < quarter {
+        half_width * y / quarter.max(1)
+    } else if y > rect.height() as i32 - quarter {
+        half_width * (rect.height() as i32 - y) / quarter.max(1)
     } else {
-        let (x, y) = one_d_to_two_d(game.current_selection);
-        canvas.copy(&textures[4], None, Some(Rect::new(32 * (x as i32), HEIGHT_PLAY_AREA_START as i32 + (y as i32) * 32, 32, 32))).expect("Couldn't copy canvas");
+        half_width
+    }
+}
+
+fn fill_hex(canvas: &mut Canvas<Window>, rect: Rect) {
+    let centre = rect.center().x();
+    for y in 0..rect.height() as i32 {
+        let half_width = hex_half_width(rect, y);
+        let _ = canvas.draw_line((centre - half_width, rect.y() + y), (centre + half_width - 1, rect.y() + y));
     }
 }
 
+fn draw_hex_outline(canvas: &mut Canvas<Window>, rect: Rect) {
+    let (left, right, top, bottom, centre) = (rect.left(), rect.right() - 1, rect.top(), rect.bottom() - 1, rect.center().x());
+    let (upper, lower) = (top + rect.height() as i32 / 4, bottom - rect.height() as i32 / 4);
+    let points: [Point; 7] = [(centre, top), (right, upper), (right, lower), (centre, bottom), (left, lower), (left, upper), (centre, top)].map(Point::from);
+    let _ = canvas.draw_lines(&points[..]);
+}
+
+// Outline of alternating dashes, so it shows up against any colour
+fn draw_patterned_rect(canvas: &mut Canvas<Window>, rect: Rect, (first, second): (Color, Color), thickness: u32) {
+    const DASH: i32 = 4;
+    for inset in 0..thickness as i32 {
+        let (left, top, right, bottom) = (rect.left() + inset, rect.top() + inset, rect.right() - 1 - inset, rect.bottom() - 1 - inset);
+        for (i, x) in (left..=right).step_by(DASH as usize).enumerate() {
+            canvas.set_draw_color(if i % 2 == 0 { first } else { second });
+            let end = (x + DASH - 1).min(right);
+            let _ = canvas.draw_line((x, top), (end, top));
+            let _ = canvas.draw_line((x, bottom), (end, bottom));
+        }
+        for (i, y) in (top..=bottom).step_by(DASH as usize).enumerate() {
+            canvas.set_draw_color(if i % 2 == 0 { first } else { second });
+            let end = (y + DASH - 1).min(bottom);
+            let _ = canvas.draw_line((left, y), (left, end));
+            let _ = canvas.draw_line((right, y), (right, end));
+        }
+    }
+}
+
+// Same centre, scaled size
+fn scale_rect(rect: Rect, scale: f32) -> Rect {
+    let (width, height) = ((rect.width() as f32 * scale).max(1.0) as u32, (rect.height() as f32 * scale).max(1.0) as u32);
+    Rect::from_center(rect.center(), width, height)
+}
+
+fn cell_rect(game: &Game, index: usize) -> Rect {
+    let (x, y) = game.field.index_to_x_y(index);
+    let cell_size = game.cell_size as i32;
+    let shift = if game.field.rules.shape == Shape::Hex && y % 2 == 1 { cell_size / 2 } else { 0 };
+    let row_spacing = row_spacing(&game.field, game.cell_size) as i32;
+    Rect::new(board_left(&game.field, game.cell_size) + cell_size * (x as i32) + shift, board_top(&game.field, game.cell_size) + (y as i32) * row_spacing, game.cell_size, cell_height(&game.field, game.cell_size))
+}
+
 fn render_end(game: &Game, canvas: &mut Canvas<Window>, font: &Font) {
+    let colors = game.config.palette.colors();
     canvas.set_blend_mode(BlendMode::Blend);
-    canvas.set_draw_color(Color::RGB(128, 128, 128));
-    let _ = canvas.fill_rect(Rect::new(0, 0, WIDTH.into(), HEIGHT.into()));
+    canvas.set_draw_color(colors.background);
+    let (width, height) = canvas.logical_size();
+    let (width, height) = (width as i32, height as i32);
+    let _ = canvas.fill_rect(Rect::new(0, 0, width as u32, height as u32));
     canvas.set_draw_color(Color::RGB(0, 0, 0));
     canvas.set_blend_mode(BlendMode::None);
 
     let texture_creator = canvas.texture_creator();
     
-    let result_surface = font.render(if game.was_winner { "Winner" } else { "Loser" }).solid(Color::RGB(0, 0, 0)).expect("Couldn't render result font");
+    let result_surface = font.render(if game.was_winner { "Winner" } else { "Loser" }).solid(colors.text).expect("Couldn't render result font");
     let result_texture = texture_creator.create_texture_from_surface(result_surface).expect("Could create result texture from font surface");
 
-    const RESULT_WIDTH: u16 = 256;
+    const RESULT_WIDTH: i32 = 256;
     const RESULT_HEIGHT: u32 = 128;
-    canvas.copy(&result_texture, None, Some(Rect::new(((WIDTH / 2) - (RESULT_WIDTH / 2)).into(), (HEIGHT_PLAY_AREA_START / 2).into(), RESULT_WIDTH.into(), RESULT_HEIGHT))).expect("Couldn't copy canvas");
+    canvas.copy(&result_texture, None, Some(Rect::new((width / 2) - (RESULT_WIDTH / 2), (HEIGHT_PLAY_AREA_START / 2) as i32, RESULT_WIDTH as u32, RESULT_HEIGHT))).expect("Couldn't copy canvas");
 
-    let play_again_surface = font.render("Play Again").solid(Color::RGB(0, 0, 0)).expect("Couldn't render play again font");
+    let play_again_surface = font.render("Play Again").solid(colors.text).expect("Couldn't render play again font");
     let play_again_texture = texture_creator.create_texture_from_surface(play_again_surface).expect("Could create play again texture from font surface");
 
-    const PLAY_AGAIN_WIDTH: u16 = 128;
+    const PLAY_AGAIN_WIDTH: i32 = 128;
     const PLAY_AGAIN_HEIGHT: u32 = 64;
-    canvas.copy(&play_again_texture, None, Some(Rect::new(((WIDTH / 2) - (PLAY_AGAIN_WIDTH / 2)).into(), (HEIGHT / 2).into(), PLAY_AGAIN_WIDTH.into(), PLAY_AGAIN_HEIGHT))).expect("Couldn't copy canvas");
+    canvas.copy(&play_again_texture, None, Some(Rect::new((width / 2) - (PLAY_AGAIN_WIDTH / 2), height / 2, PLAY_AGAIN_WIDTH as u32, PLAY_AGAIN_HEIGHT))).expect("Couldn't copy canvas");
 
-    let replay_surface = font.render("Press any button or left-click to continue").solid(Color::RGB(0, 0, 0)).expect("Couldn't render replay font");
+    let replay_surface = font.render(if game.remote.is_none() { "Press any button or left-click to continue, select for menu" } else { "Press any button or left-click to continue" }).solid(colors.text).expect("Couldn't render replay font");
     let replay_texture = texture_creator.create_texture_from_surface(replay_surface).expect("Could create replay texture from font surface");
 
-    const REPLAY_WIDTH: u16 = 384;
+    let replay_width: i32 = if game.remote.is_none() { 576 } else { 384 };
     const REPLAY_HEIGHT: u32 = 32;
-    canvas.copy(&replay_texture, None, Some(Rect::new(((WIDTH / 2) - (REPLAY_WIDTH / 2)).into(), (HEIGHT / 2) as i32 + PLAY_AGAIN_HEIGHT as i32, REPLAY_WIDTH.into(), REPLAY_HEIGHT))).expect("Couldn't copy canvas");
+    canvas.copy(&replay_texture, None, Some(Rect::new((width / 2) - (replay_width / 2), height / 2 + PLAY_AGAIN_HEIGHT as i32, replay_width as u32, REPLAY_HEIGHT))).expect("Couldn't copy canvas");
 
     let watermark_surface = font.render("Palaster").solid(Color::RGB(255, 0, 0)).expect("Couldn't render watermark font");
     let watermark_texture = texture_creator.create_texture_from_surface(watermark_surface).expect("Could create watermark texture from font surface");
 
     const WATERMARK_WIDTH: u32 = 64;
     const WATERMARK_HEIGHT: u32 = 32;
-    canvas.copy(&watermark_texture, None, Some(Rect::new(0, (HEIGHT as u32 - WATERMARK_HEIGHT) as i32, WATERMARK_WIDTH, WATERMARK_HEIGHT))).expect("Couldn't copy canvas");
+    canvas.copy(&watermark_texture, None, Some(Rect::new(0, height - WATERMARK_HEIGHT as i32, WATERMARK_WIDTH, WATERMARK_HEIGHT))).expect("Couldn't copy canvas");
 
-    let duration_surface = font.render(&format!("Time: {}", if let Some(game_duration) = game.game_duration { game_duration.as_secs() } else { 0 })).solid(Color::RGB(0, 0, 0)).expect("Couldn't render duration font");
+    let duration_surface = font.render(&format!("Time: {}", if let Some(game_duration) = game.game_duration { game_duration.as_secs() } else { 0 })).solid(colors.text).expect("Couldn't render duration font");
     let duration_texture = texture_creator.create_texture_from_surface(duration_surface).expect("Could create duration texture from font surface");
 
-    const DURATION_WIDTH: u16 = 128;
+    const DURATION_WIDTH: i32 = 128;
     const DURATION_HEIGHT: u32 = 64;
-    canvas.copy(&duration_texture, None, Some(Rect::new(((WIDTH / 2) - (DURATION_WIDTH / 2)).into(), (HEIGHT / 4).into(), DURATION_WIDTH.into(), DURATION_HEIGHT))).expect("Couldn't copy canvas");
-
-    if !game.was_winner {
+    canvas.copy(&duration_texture, None, Some(Rect::new((width / 2) - (DURATION_WIDTH / 2), height / 4, DURATION_WIDTH as u32, DURATION_HEIGHT))).expect("Couldn't copy canvas");
+
+    let mut stats_y = height / 4 + DURATION_HEIGHT as i32;
+    if let Some(endless) = &game.endless {
+        let cleared_surface = font.render(&format!("Safe cells cleared: {}", endless.cleared)).solid(colors.text).expect("Couldn't render cleared font");
+        let cleared_texture = texture_creator.create_texture_from_surface(cleared_surface).expect("Could create cleared texture from font surface");
+
+        const CLEARED_WIDTH: i32 = 256;
+        const CLEARED_HEIGHT: u32 = 64;
+        canvas.copy(&cleared_texture, None, Some(Rect::new((width / 2) - (CLEARED_WIDTH / 2), stats_y, CLEARED_WIDTH as u32, CLEARED_HEIGHT))).expect("Couldn't copy canvas");
+        stats_y += CLEARED_HEIGHT as i32;
+    } else if !game.was_winner {
         let mut flagged_mine_counter = 0;
-        for cell in game.field.cells {
-            if cell.flagged && cell.has_mine {
-                flagged_mine_counter += 1;
-            }
+        for cell in &game.field.cells {
+            flagged_mine_counter += cell.flags.min(cell.mines) as u32;
         }
 
-        let correct_surface = font.render(&format!("Correctly flagged mines: {}", flagged_mine_counter)).solid(Color::RGB(0, 0, 0)).expect("Couldn't render correct font");
+        let correct_surface = font.render(&format!("Correctly flagged mines: {}", flagged_mine_counter)).solid(colors.text).expect("Couldn't render correct font");
         let correct_texture = texture_creator.create_texture_from_surface(correct_surface).expect("Could create correct texture from font surface");
 
-        const CORRECT_WIDTH: u16 = 256;
+        const CORRECT_WIDTH: i32 = 256;
         const CORRECT_HEIGHT: u32 = 64;
-        canvas.copy(&correct_texture, None, Some(Rect::new(((WIDTH / 2) - (CORRECT_WIDTH / 2)).into(), (HEIGHT / 4) as i32 + DURATION_HEIGHT as i32, CORRECT_WIDTH.into(), CORRECT_HEIGHT))).expect("Couldn't copy canvas");
+        canvas.copy(&correct_texture, None, Some(Rect::new((width / 2) - (CORRECT_WIDTH / 2), stats_y, CORRECT_WIDTH as u32, CORRECT_HEIGHT))).expect("Couldn't copy canvas");
+        stats_y += CORRECT_HEIGHT as i32;
+    }
+
+    if game.remote.is_none() && game.config.lives > 0 {
+        let lives_surface = font.render(&format!("Lives used: {} of {}", game.lives_used, game.config.lives)).solid(colors.text).expect("Couldn't render lives font");
+        let lives_texture = texture_creator.create_texture_from_surface(lives_surface).expect("Could create lives texture from font surface");
+
+        const LIVES_WIDTH: i32 = 192;
+        const LIVES_HEIGHT: u32 = 48;
+        canvas.copy(&lives_texture, None, Some(Rect::new((width / 2) - (LIVES_WIDTH / 2), stats_y, LIVES_WIDTH as u32, LIVES_HEIGHT))).expect("Couldn't copy canvas");
     }
 }
 
+fn render_menu(game: &Game, canvas: &mut Canvas<Window>, font: &Font) {
+    let colors = game.config.palette.colors();
+    canvas.set_draw_color(colors.background);
+    let (width, height) = canvas.logical_size();
+    let _ = canvas.fill_rect(Rect::new(0, 0, width, height));
+
+    const TITLE_HEIGHT: u32 = 96;
+    render_text(canvas, font, game.scene.title(), colors.text, width as i32 / 2, (menu::MENU_TOP - TITLE_HEIGHT as i32) / 2, TITLE_HEIGHT);
+
+    let row_height = game.scene.row_height();
+    let row_text_height = row_height as u32 * 5 / 8;
+    let first_row = game.first_menu_row();
+    for (i, (_, row)) in game.menu_rows().iter().enumerate().skip(first_row).take(menu::visible_rows(row_height)) {
+        let y = menu::MENU_TOP + ((i - first_row) as i32) * row_height;
+        if i == game.menu_selection {
+            canvas.set_draw_color(colors.selection);
+            let _ = canvas.fill_rect(Rect::new(0, y, width, row_height as u32));
+        }
+        let color = if i == game.menu_selection { colors.highlight } else { colors.text };
+        render_text(canvas, font, row, color, width as i32 / 2, y + (row_height - row_text_height as i32) / 2, row_text_height);
+    }
+
+    const WATERMARK_WIDTH: u32 = 64;
+    const WATERMARK_HEIGHT: u32 = 32;
+    render_text(canvas, font, "Palaster", Color::RGB(255, 0, 0), WATERMARK_WIDTH as i32 / 2, height as i32 - WATERMARK_HEIGHT as i32, WATERMARK_HEIGHT);
+}
+
+// Draws text centered on center_x, scaled to the given height while keeping its aspect ratio
+fn render_text(canvas: &mut Canvas<Window>, font: &Font, text: &str, color: Color, center_x: i32, y: i32, height: u32) {
+    let texture_creator = canvas.texture_creator();
+    let surface = font.render(text).solid(color).expect("Couldn't render text font");
+    let width = surface.width() * height / surface.height().max(1);
+    let texture = texture_creator.create_texture_from_surface(surface).expect("Could create text texture from font surface");
+    canvas.copy(&texture, None, Some(Rect::new(center_x - (width as i32 / 2), y, width, height))).expect("Couldn't copy canvas");
+}
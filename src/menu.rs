@@ -0,0 +1,743 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::announce::ANNOUNCEMENTS;
+use crate::config::Config;
+use crate::field::{Field, Rules, MAX_MINES_PER_CELL};
+use crate::input::{Action, Bindings, ACTIONS};
+use crate::music::REPEATS;
+use crate::palette::PALETTES;
+use crate::sound::Sound;
+use crate::theme::available_themes;
+use crate::topology::{Shape, NEIGHBOURHOODS, SHAPES};
+use crate::Game;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Scene {
+    Menu,
+    CustomBoard,
+    Statistics,
+    Settings,
+    Bindings,
+    Paused,
+    Playing,
+    End,
+}
+
+impl Scene {
+    pub fn title(&self) -> &'static str {
+        match self {
+            Scene::Menu => "Minesweeper",
+            Scene::CustomBoard => "Custom Board",
+            Scene::Statistics => "Statistics",
+            Scene::Settings => "Settings",
+            Scene::Bindings => "Key Bindings",
+            Scene::Paused => "Paused",
+            Scene::Playing | Scene::End => "",
+        }
+    }
+
+    pub fn is_menu(&self) -> bool {
+        !matches!(self, Scene::Playing | Scene::End)
+    }
+
+    pub fn row_height(&self) -> i32 {
+        match self {
+            Scene::Settings | Scene::Bindings => 48,
+            _ => 64,
+        }
+    }
+}
+
+// What each row of a menu does, rows are picked by what they are rather than where they are
+#[derive(Clone, Copy, PartialEq)]
+pub enum Row {
+    Menu(MenuRow),
+    CustomBoard(CustomBoardRow),
+    Settings(SettingsRow),
+    Bindings(BindingsRow),
+    Paused(PausedRow),
+    // One difficulty's statistics, there's nothing to do with it
+    Record,
+    Back,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum MenuRow {
+    NewGame,
+    EndlessGame,
+    Difficulty,
+    Board,
+    Layers,
+    Neighbours,
+    MinesPerCell,
+    Lives,
+    CustomBoard,
+    Statistics,
+    Settings,
+    Quit,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum CustomBoardRow {
+    Width,
+    Height,
+    Mines,
+    Start,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum SettingsRow {
+    Music,
+    MusicVolume,
+    Shuffle,
+    Repeat,
+    SoundEffects,
+    SoundVolume,
+    WindowScale,
+    Scaling,
+    Fullscreen,
+    Theme,
+    Colours,
+    Announcements,
+    Animations,
+    QuestionMarks,
+    FirstClickSafe,
+    RepeatDelay,
+    RepeatRate,
+    KeyBindings,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum BindingsRow {
+    Action(Action),
+    Reset,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum PausedRow {
+    Resume,
+    Restart,
+    Settings,
+    QuitToMenu,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub struct Difficulty {
+    pub name: &'static str,
+    pub width: usize,
+    pub height: usize,
+    pub mines: u32,
+}
+
+pub const DIFFICULTIES: [Difficulty; 4] = [
+    Difficulty { name: "Beginner", width: 9, height: 9, mines: 10 },
+    Difficulty { name: "Intermediate", width: 16, height: 16, mines: 40 },
+    Difficulty { name: "Expert", width: 30, height: 16, mines: 99 },
+    Difficulty { name: "Classic", width: 24, height: 24, mines: 99 },
+];
+
+// Index into Statistics::records used for custom boards
+pub const CUSTOM_DIFFICULTY: usize = DIFFICULTIES.len();
+
+const CUSTOM_MIN_SIZE: usize = 5;
+const CUSTOM_MAX_WIDTH: usize = 60;
+const CUSTOM_MAX_HEIGHT: usize = 40;
+
+const MAX_WINDOW_SCALE: u32 = 4;
+
+const MAX_LAYERS: usize = 8;
+const MAX_LIVES: u32 = 9;
+
+// Repeat delay is changed 50 milliseconds at a time and the rate 5 repeats at a time
+const REPEAT_DELAY_STEP: u32 = 50;
+const MIN_REPEAT_DELAY: u32 = 100;
+const MAX_REPEAT_DELAY: u32 = 1_000;
+const REPEAT_RATE_STEP: u32 = 5;
+const MAX_REPEAT_RATE: u32 = 60;
+
+pub const MENU_TOP: i32 = 192;
+// Room left under the rows for the watermark
+const MENU_BOTTOM: i32 = 32;
+
+// Menus with more rows than fit scroll to keep the selected row in view
+pub fn visible_rows(row_height: i32) -> usize {
+    ((crate::MENU_HEIGHT as i32 - MENU_TOP - MENU_BOTTOM) / row_height) as usize
+}
+
+pub fn menu_row_at(y: i32, row_height: i32, first_row: usize, number_of_rows: usize) -> Option<usize> {
+    if y < MENU_TOP {
+        return None;
+    }
+    let row = ((y - MENU_TOP) / row_height) as usize;
+    if row >= visible_rows(row_height) {
+        return None;
+    }
+    Some(first_row + row).filter(|row| *row < number_of_rows)
+}
+
+#[derive(Clone, Copy, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Record {
+    pub played: u32,
+    pub won: u32,
+    pub best_time: Option<Duration>,
+}
+
+#[derive(Clone, Copy, Default)]
+pub struct Statistics {
+    pub records: [Record; DIFFICULTIES.len() + 1],
+}
+
+// Names the records are saved under, the custom board's last
+fn record_names() -> impl Iterator<Item = &'static str> {
+    DIFFICULTIES.iter().map(|difficulty| difficulty.name).chain(["Custom"])
+}
+
+impl Statistics {
+    pub fn path() -> Option<PathBuf> {
+        Some(Config::directory()?.join("statistics.toml"))
+    }
+
+    // Saved by board name, so new difficulties don't mix up the records of the others
+    fn from_toml(contents: &str) -> Result<Statistics, toml::de::Error> {
+        let mut saved: HashMap<String, Record> = toml::from_str(contents)?;
+        let mut statistics = Statistics::default();
+        for (record, name) in statistics.records.iter_mut().zip(record_names()) {
+            *record = saved.remove(name).unwrap_or_default();
+        }
+        Ok(statistics)
+    }
+
+    fn to_toml(self) -> Result<String, toml::ser::Error> {
+        toml::to_string_pretty(&record_names().zip(self.records).collect::<HashMap<&str, Record>>())
+    }
+
+    // Missing or broken statistics files start from nothing
+    pub fn load() -> Statistics {
+        let Some(path) = Statistics::path() else {
+            return Statistics::default();
+        };
+        let Ok(contents) = fs::read_to_string(&path) else {
+            return Statistics::default();
+        };
+        match Statistics::from_toml(&contents) {
+            Ok(statistics) => statistics,
+            Err(e) => {
+                eprintln!("Couldn't parse {}: {}", path.display(), e);
+                Statistics::default()
+            },
+        }
+    }
+
+    pub fn save(&self) {
+        let Some(path) = Statistics::path() else {
+            return;
+        };
+        let contents = match self.to_toml() {
+            Ok(contents) => contents,
+            Err(e) => {
+                eprintln!("Couldn't serialize statistics: {}", e);
+                return;
+            },
+        };
+        if let Some(parent) = path.parent() {
+            let _ = fs::create_dir_all(parent);
+        }
+        if let Err(e) = fs::write(&path, contents) {
+            eprintln!("Couldn't write {}: {}", path.display(), e);
+        }
+    }
+
+    pub fn record(&mut self, difficulty: usize, was_winner: bool, duration: Option<Duration>) {
+        let record = &mut self.records[difficulty];
+        record.played += 1;
+        if was_winner {
+            record.won += 1;
+            if let Some(duration) = duration {
+                if record.best_time.is_none_or(|best_time| duration < best_time) {
+                    record.best_time = Some(duration);
+                }
+            }
+        }
+    }
+}
+
+impl Game {
+    pub fn set_scene(&mut self, scene: Scene) {
+        self.scene = scene;
+        self.rebinding = None;
+        self.menu_selection = 0;
+        self.inputs = [None; ACTIONS.len()];
+        self.mouse_press = None;
+    }
+
+    pub fn load_config(&mut self, config: Config) {
+        if let Some(difficulty) = (0..=CUSTOM_DIFFICULTY).find(|difficulty| self.board(*difficulty).name == config.board) {
+            self.menu_difficulty = difficulty;
+        }
+        self.custom.width = config.custom_width.clamp(CUSTOM_MIN_SIZE, CUSTOM_MAX_WIDTH);
+        self.custom.height = config.custom_height.clamp(CUSTOM_MIN_SIZE, CUSTOM_MAX_HEIGHT);
+        self.custom.mines = config.custom_mines.clamp(1, Field::max_mines(self.custom.width, self.custom.height));
+        self.config = config;
+        self.config.window_scale = self.config.window_scale.clamp(1, MAX_WINDOW_SCALE);
+        self.config.layers = self.config.layers.clamp(2, MAX_LAYERS);
+        self.config.mines_per_cell = self.config.mines_per_cell.clamp(1, MAX_MINES_PER_CELL);
+        self.config.lives = self.config.lives.min(MAX_LIVES);
+        self.config.music_volume = self.config.music_volume.min(100);
+        self.config.sound_volume = self.config.sound_volume.min(100);
+        self.config.repeat_delay = self.config.repeat_delay.clamp(MIN_REPEAT_DELAY, MAX_REPEAT_DELAY) / REPEAT_DELAY_STEP * REPEAT_DELAY_STEP;
+        self.config.repeat_rate = self.config.repeat_rate.clamp(REPEAT_RATE_STEP, MAX_REPEAT_RATE) / REPEAT_RATE_STEP * REPEAT_RATE_STEP;
+    }
+
+    pub fn toggle_fullscreen(&mut self) {
+        self.config.fullscreen = !self.config.fullscreen;
+        self.save_config();
+    }
+
+    fn save_config(&mut self) {
+        self.config.custom_width = self.custom.width;
+        self.config.custom_height = self.custom.height;
+        self.config.custom_mines = self.custom.mines;
+        self.config.save();
+    }
+
+    pub fn pause(&mut self) {
+        if self.scene == Scene::Playing {
+            self.clock.pause();
+            self.set_scene(Scene::Paused);
+        }
+    }
+
+    pub fn resume(&mut self) {
+        self.clock.resume();
+        self.set_scene(Scene::Playing);
+    }
+
+    fn open_settings(&mut self) {
+        self.settings_return = self.scene;
+        self.set_scene(Scene::Settings);
+    }
+
+    // Where B and the Back rows lead
+    fn back(&mut self) {
+        match self.scene {
+            Scene::Paused => self.resume(),
+            Scene::Settings => {
+                self.save_config();
+                self.set_scene(self.settings_return);
+            },
+            Scene::Bindings => self.set_scene(Scene::Settings),
+            _ => self.set_scene(Scene::Menu),
+        }
+    }
+
+    fn board(&self, difficulty: usize) -> Difficulty {
+        if difficulty == CUSTOM_DIFFICULTY {
+            self.custom
+        } else {
+            DIFFICULTIES[difficulty]
+        }
+    }
+
+    pub fn start_game(&mut self, difficulty: usize) {
+        self.difficulty = difficulty;
+        // The board last played is the one picked next time, custom boards included
+        self.menu_difficulty = difficulty;
+        let board = self.board(difficulty);
+        self.config.board = board.name.to_string();
+        let rules = self.rules();
+        // Each layer gets as many mines as the flat board would
+        self.reset(Field::new(board.width, board.height, board.mines * rules.layers as u32, rules));
+        self.save_config();
+    }
+
+    fn rules(&self) -> Rules {
+        Rules {
+            shape: self.config.shape,
+            layers: if self.config.shape == Shape::Layers { self.config.layers } else { 1 },
+            neighbourhood: self.config.neighbourhood,
+            mines_per_cell: self.config.mines_per_cell,
+        }
+    }
+
+    // Each row with the label drawn for it
+    pub fn menu_rows(&self) -> Vec<(Row, String)> {
+        match self.scene {
+            Scene::Menu => vec![
+                (Row::Menu(MenuRow::NewGame), "New Game".to_string()),
+                // Endless boards are always flat, whatever board is picked
+                (Row::Menu(MenuRow::EndlessGame), if matches!(self.config.shape, Shape::Hex | Shape::Layers) { "Endless Game (Flat)".to_string() } else { "Endless Game".to_string() }),
+                (Row::Menu(MenuRow::Difficulty), format!("Difficulty: < {} >", self.board(self.menu_difficulty).name)),
+                (Row::Menu(MenuRow::Board), format!("Board: < {} >", self.config.shape.name())),
+                (Row::Menu(MenuRow::Layers), format!("3D Layers: < {} >", self.config.layers)),
+                (Row::Menu(MenuRow::Neighbours), format!("Neighbours: < {} >", self.config.neighbourhood.name())),
+                (Row::Menu(MenuRow::MinesPerCell), format!("Mines Per Cell: < {} >", self.config.mines_per_cell)),
+                (Row::Menu(MenuRow::Lives), format!("Lives: < {} >", if self.config.lives == 0 { "Off".to_string() } else { self.config.lives.to_string() })),
+                (Row::Menu(MenuRow::CustomBoard), "Custom Board".to_string()),
+                (Row::Menu(MenuRow::Statistics), "Statistics".to_string()),
+                (Row::Menu(MenuRow::Settings), "Settings".to_string()),
+                (Row::Menu(MenuRow::Quit), "Quit".to_string()),
+            ],
+            Scene::CustomBoard => vec![
+                (Row::CustomBoard(CustomBoardRow::Width), format!("Width: < {} >", self.custom.width)),
+                (Row::CustomBoard(CustomBoardRow::Height), format!("Height: < {} >", self.custom.height)),
+                (Row::CustomBoard(CustomBoardRow::Mines), format!("Mines: < {} >", self.custom.mines)),
+                (Row::CustomBoard(CustomBoardRow::Start), "Start".to_string()),
+                (Row::Back, "Back".to_string()),
+            ],
+            Scene::Statistics => {
+                let mut rows: Vec<(Row, String)> = record_names()
+                    .zip(self.statistics.records.iter())
+                    .map(|(name, record)| (Row::Record, format!("{}: {} of {} won, best {}", name, record.won, record.played, match record.best_time {
+                        Some(best_time) => format!("{}s", best_time.as_secs()),
+                        None => "-".to_string(),
+                    })))
+                    .collect();
+                rows.push((Row::Back, "Back".to_string()));
+                rows
+            },
+            Scene::Settings => vec![
+                (Row::Settings(SettingsRow::Music), format!("Music: {}", on_off(self.config.music))),
+                (Row::Settings(SettingsRow::MusicVolume), format!("Music Volume: < {}% >", self.config.music_volume)),
+                (Row::Settings(SettingsRow::Shuffle), format!("Shuffle: {}", on_off(self.config.music_shuffle))),
+                (Row::Settings(SettingsRow::Repeat), format!("Repeat: < {} >", self.config.music_repeat.name())),
+                (Row::Settings(SettingsRow::SoundEffects), format!("Sound Effects: {}", on_off(self.config.sound_effects))),
+                (Row::Settings(SettingsRow::SoundVolume), format!("Effects Volume: < {}% >", self.config.sound_volume)),
+                (Row::Settings(SettingsRow::WindowScale), format!("Window Scale: < {}x >", self.config.window_scale)),
+                (Row::Settings(SettingsRow::Scaling), format!("Scaling: {}", if self.config.integer_scaling { "Integer" } else { "Fractional" })),
+                (Row::Settings(SettingsRow::Fullscreen), format!("Fullscreen: {}", on_off(self.config.fullscreen))),
+                (Row::Settings(SettingsRow::Theme), format!("Theme: < {} >", self.config.theme)),
+                (Row::Settings(SettingsRow::Colours), format!("Colours: < {} >", self.config.palette.name())),
+                (Row::Settings(SettingsRow::Announcements), format!("Announcements: < {} >", self.config.announcements.name())),
+                (Row::Settings(SettingsRow::Animations), format!("Animations: {}", on_off(self.config.animations))),
+                (Row::Settings(SettingsRow::QuestionMarks), format!("Question Marks: {}", on_off(self.config.question_marks))),
+                (Row::Settings(SettingsRow::FirstClickSafe), format!("First Click Safe: {}", on_off(self.config.first_click_safe))),
+                (Row::Settings(SettingsRow::RepeatDelay), format!("Repeat Delay: < {} ms >", self.config.repeat_delay)),
+                (Row::Settings(SettingsRow::RepeatRate), format!("Repeat Rate: < {} per second >", self.config.repeat_rate)),
+                (Row::Settings(SettingsRow::KeyBindings), "Key Bindings".to_string()),
+                (Row::Back, "Back".to_string()),
+            ],
+            Scene::Bindings => {
+                let mut rows: Vec<(Row, String)> = ACTIONS.iter()
+                    .map(|action| (Row::Bindings(BindingsRow::Action(*action)), if self.rebinding == Some(*action) {
+                        format!("{}: press a key or button", action.name())
+                    } else {
+                        let keys = self.config.key_bindings.get(*action).join(", ");
+                        let buttons = self.config.button_bindings.get(*action).join(", ");
+                        format!("{}: {} / {}", action.name(), keys, buttons)
+                    }))
+                    .collect();
+                rows.push((Row::Bindings(BindingsRow::Reset), "Reset to Defaults".to_string()));
+                rows.push((Row::Back, "Back".to_string()));
+                rows
+            },
+            Scene::Paused => vec![
+                (Row::Paused(PausedRow::Resume), "Resume".to_string()),
+                (Row::Paused(PausedRow::Restart), "Restart".to_string()),
+                (Row::Paused(PausedRow::Settings), "Settings".to_string()),
+                (Row::Paused(PausedRow::QuitToMenu), "Quit to Menu".to_string()),
+            ],
+            Scene::Playing | Scene::End => Vec::new(),
+        }
+    }
+
+    pub fn menu_input(&mut self, action: Action) {
+        let number_of_rows = self.menu_rows().len();
+        match action {
+            Action::MoveUp => self.menu_selection = (self.menu_selection + number_of_rows - 1) % number_of_rows,
+            Action::MoveDown => self.menu_selection = (self.menu_selection + 1) % number_of_rows,
+            Action::MoveLeft => self.activate_menu_row(self.menu_selection, -1),
+            Action::MoveRight => self.activate_menu_row(self.menu_selection, 1),
+            // Pause closes the pause menu like it opened it
+            Action::Pause if self.scene == Scene::Paused => self.resume(),
+            Action::Reveal | Action::Pause => self.activate_menu_row(self.menu_selection, 0),
+            Action::Flag if self.scene != Scene::Menu => self.back(),
+            _ => return,
+        }
+        self.sounds.push(Sound::Navigate);
+    }
+
+    // The next key pressed while rebinding is bound instead of acting, Escape cancels
+    pub fn bind_key(&mut self, name: String) {
+        if let Some(action) = self.rebinding.take() {
+            if name != "Escape" {
+                self.config.key_bindings.bind(action, name);
+            }
+        }
+    }
+
+    pub fn bind_button(&mut self, name: String) {
+        if let Some(action) = self.rebinding.take() {
+            self.config.button_bindings.bind(action, name);
+        }
+    }
+
+    // First row shown, just enough to keep the selected row on screen
+    pub fn first_menu_row(&self) -> usize {
+        (self.menu_selection + 1).saturating_sub(visible_rows(self.scene.row_height()))
+    }
+
+    pub fn menu_hover(&mut self, y: i32) {
+        if let Some(row) = menu_row_at(y, self.scene.row_height(), self.first_menu_row(), self.menu_rows().len()) {
+            self.menu_selection = row;
+        }
+    }
+
+    pub fn menu_click(&mut self, y: i32, direction: i8) {
+        if let Some(row) = menu_row_at(y, self.scene.row_height(), self.first_menu_row(), self.menu_rows().len()) {
+            self.menu_selection = row;
+            self.sounds.push(Sound::Navigate);
+            self.activate_menu_row(row, direction);
+        }
+    }
+
+    pub fn toggle_mute(&mut self) {
+        self.config.muted = !self.config.muted;
+        self.save_config();
+    }
+
+    pub fn toggle_music(&mut self) {
+        self.config.music = !self.config.music;
+        self.save_config();
+    }
+
+    // A direction of 0 confirms the row, -1 and 1 change the value shown on it, confirming a value steps it forward
+    fn activate_menu_row(&mut self, index: usize, direction: i8) {
+        let Some((row, _)) = self.menu_rows().into_iter().nth(index) else {
+            return;
+        };
+        match row {
+            Row::Menu(MenuRow::NewGame) if direction == 0 => self.start_game(self.menu_difficulty),
+            Row::Menu(MenuRow::EndlessGame) if direction == 0 => self.start_endless(self.endless_seed(), self.rules()),
+            Row::Menu(MenuRow::Difficulty) => {
+                // Custom comes after the presets
+                let difficulties = CUSTOM_DIFFICULTY + 1;
+                self.menu_difficulty = if direction < 0 {
+                    (self.menu_difficulty + difficulties - 1) % difficulties
+                } else {
+                    (self.menu_difficulty + 1) % difficulties
+                };
+            },
+            Row::Menu(MenuRow::Board) => {
+                let current = SHAPES.iter().position(|shape| *shape == self.config.shape).unwrap_or(0);
+                let next = if direction < 0 {
+                    (current + SHAPES.len() - 1) % SHAPES.len()
+                } else {
+                    (current + 1) % SHAPES.len()
+                };
+                self.config.shape = SHAPES[next];
+            },
+            Row::Menu(MenuRow::Layers) => self.config.layers = step(self.config.layers, direction, 2, MAX_LAYERS),
+            Row::Menu(MenuRow::Neighbours) => {
+                let current = NEIGHBOURHOODS.iter().position(|neighbourhood| *neighbourhood == self.config.neighbourhood).unwrap_or(0);
+                let next = if direction < 0 {
+                    (current + NEIGHBOURHOODS.len() - 1) % NEIGHBOURHOODS.len()
+                } else {
+                    (current + 1) % NEIGHBOURHOODS.len()
+                };
+                self.config.neighbourhood = NEIGHBOURHOODS[next];
+            },
+            Row::Menu(MenuRow::MinesPerCell) => self.config.mines_per_cell = step(self.config.mines_per_cell as usize, direction, 1, MAX_MINES_PER_CELL as usize) as u8,
+            Row::Menu(MenuRow::Lives) => self.config.lives = step(self.config.lives as usize, direction, 0, MAX_LIVES as usize) as u32,
+            Row::Menu(MenuRow::CustomBoard) if direction == 0 => self.set_scene(Scene::CustomBoard),
+            Row::Menu(MenuRow::Statistics) if direction == 0 => self.set_scene(Scene::Statistics),
+            Row::Menu(MenuRow::Settings) if direction == 0 => self.open_settings(),
+            Row::Menu(MenuRow::Quit) if direction == 0 => self.should_quit = true,
+            Row::CustomBoard(CustomBoardRow::Width) => {
+                self.custom.width = step(self.custom.width, direction, CUSTOM_MIN_SIZE, CUSTOM_MAX_WIDTH);
+                self.custom.mines = self.custom.mines.min(Field::max_mines(self.custom.width, self.custom.height));
+            },
+            Row::CustomBoard(CustomBoardRow::Height) => {
+                self.custom.height = step(self.custom.height, direction, CUSTOM_MIN_SIZE, CUSTOM_MAX_HEIGHT);
+                self.custom.mines = self.custom.mines.min(Field::max_mines(self.custom.width, self.custom.height));
+            },
+            Row::CustomBoard(CustomBoardRow::Mines) => {
+                self.custom.mines = step(self.custom.mines as usize, direction, 1, Field::max_mines(self.custom.width, self.custom.height) as usize) as u32;
+            },
+            Row::CustomBoard(CustomBoardRow::Start) if direction == 0 => self.start_game(CUSTOM_DIFFICULTY),
+            Row::Settings(SettingsRow::Music) => self.config.music = !self.config.music,
+            Row::Settings(SettingsRow::MusicVolume) => {
+                self.config.music_volume = if direction < 0 {
+                    self.config.music_volume.saturating_sub(10)
+                } else {
+                    (self.config.music_volume + 10).min(100)
+                };
+            },
+            Row::Settings(SettingsRow::Shuffle) => self.config.music_shuffle = !self.config.music_shuffle,
+            Row::Settings(SettingsRow::Repeat) => {
+                let current = REPEATS.iter().position(|repeat| *repeat == self.config.music_repeat).unwrap_or(0);
+                let next = if direction < 0 {
+                    (current + REPEATS.len() - 1) % REPEATS.len()
+                } else {
+                    (current + 1) % REPEATS.len()
+                };
+                self.config.music_repeat = REPEATS[next];
+            },
+            Row::Settings(SettingsRow::SoundEffects) => self.config.sound_effects = !self.config.sound_effects,
+            Row::Settings(SettingsRow::SoundVolume) => {
+                self.config.sound_volume = if direction < 0 {
+                    self.config.sound_volume.saturating_sub(10)
+                } else {
+                    (self.config.sound_volume + 10).min(100)
+                };
+            },
+            Row::Settings(SettingsRow::WindowScale) => {
+                self.config.window_scale = step(self.config.window_scale as usize, direction, 1, MAX_WINDOW_SCALE as usize) as u32;
+            },
+            Row::Settings(SettingsRow::Scaling) => self.config.integer_scaling = !self.config.integer_scaling,
+            Row::Settings(SettingsRow::Fullscreen) => self.config.fullscreen = !self.config.fullscreen,
+            Row::Settings(SettingsRow::Theme) => {
+                // The theme itself is loaded by the render loop
+                let themes = available_themes();
+                let current = themes.iter().position(|theme| *theme == self.config.theme).unwrap_or(0);
+                let next = if direction < 0 {
+                    (current + themes.len() - 1) % themes.len()
+                } else {
+                    (current + 1) % themes.len()
+                };
+                self.config.theme = themes[next].clone();
+            },
+            Row::Settings(SettingsRow::Colours) => {
+                let current = PALETTES.iter().position(|palette| *palette == self.config.palette).unwrap_or(0);
+                let next = if direction < 0 {
+                    (current + PALETTES.len() - 1) % PALETTES.len()
+                } else {
+                    (current + 1) % PALETTES.len()
+                };
+                self.config.palette = PALETTES[next];
+            },
+            Row::Settings(SettingsRow::Announcements) => {
+                let current = ANNOUNCEMENTS.iter().position(|announcements| *announcements == self.config.announcements).unwrap_or(0);
+                let next = if direction < 0 {
+                    (current + ANNOUNCEMENTS.len() - 1) % ANNOUNCEMENTS.len()
+                } else {
+                    (current + 1) % ANNOUNCEMENTS.len()
+                };
+                self.config.announcements = ANNOUNCEMENTS[next];
+            },
+            Row::Settings(SettingsRow::Animations) => self.config.animations = !self.config.animations,
+            Row::Settings(SettingsRow::QuestionMarks) => self.config.question_marks = !self.config.question_marks,
+            Row::Settings(SettingsRow::FirstClickSafe) => self.config.first_click_safe = !self.config.first_click_safe,
+            Row::Settings(SettingsRow::RepeatDelay) => {
+                let delay = step((self.config.repeat_delay / REPEAT_DELAY_STEP) as usize, direction, (MIN_REPEAT_DELAY / REPEAT_DELAY_STEP) as usize, (MAX_REPEAT_DELAY / REPEAT_DELAY_STEP) as usize);
+                self.config.repeat_delay = delay as u32 * REPEAT_DELAY_STEP;
+            },
+            Row::Settings(SettingsRow::RepeatRate) => {
+                let rate = step((self.config.repeat_rate / REPEAT_RATE_STEP) as usize, direction, 1, (MAX_REPEAT_RATE / REPEAT_RATE_STEP) as usize);
+                self.config.repeat_rate = rate as u32 * REPEAT_RATE_STEP;
+            },
+            Row::Settings(SettingsRow::KeyBindings) if direction == 0 => self.set_scene(Scene::Bindings),
+            Row::Back if direction == 0 => self.back(),
+            Row::Bindings(BindingsRow::Action(action)) => {
+                if direction < 0 {
+                    // Clearing leaves the action to be bound again from scratch
+                    self.config.key_bindings.get_mut(action).clear();
+                    self.config.button_bindings.get_mut(action).clear();
+                } else {
+                    self.rebinding = Some(action);
+                }
+            },
+            Row::Bindings(BindingsRow::Reset) if direction == 0 => {
+                self.config.key_bindings = Bindings::keyboard();
+                self.config.button_bindings = Bindings::controller();
+            },
+            Row::Paused(PausedRow::Resume) if direction == 0 => self.resume(),
+            Row::Paused(PausedRow::Restart) if direction == 0 => {
+                self.restart();
+                // The server only starts a new game once the current one is over
+                if self.remote.is_some() {
+                    self.resume();
+                }
+            },
+            Row::Paused(PausedRow::Settings) if direction == 0 => self.open_settings(),
+            Row::Paused(PausedRow::QuitToMenu) if direction == 0 => {
+                self.remote = None;
+                self.set_scene(Scene::Menu);
+            },
+            _ => {},
+        }
+    }
+}
+
+fn on_off(value: bool) -> &'static str {
+    if value {
+        "On"
+    } else {
+        "Off"
+    }
+}
+
+fn step(value: usize, direction: i8, min: usize, max: usize) -> usize {
+    if direction < 0 {
+        value.saturating_sub(1).max(min)
+    } else {
+        (value + 1).min(max)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn row_index(game: &Game, row: Row) -> usize {
+        game.menu_rows().iter().position(|(other, _)| *other == row).expect("Couldn't find row")
+    }
+
+    #[test]
+    fn rows_are_only_listed_once() {
+        let mut game = Game::new(Field::empty(9, 9, 10));
+        for scene in [Scene::Menu, Scene::CustomBoard, Scene::Statistics, Scene::Settings, Scene::Bindings, Scene::Paused] {
+            game.set_scene(scene);
+            let rows: Vec<Row> = game.menu_rows().into_iter().map(|(row, _)| row).filter(|row| *row != Row::Record).collect();
+            assert!(rows.iter().enumerate().all(|(i, row)| !rows[i + 1..].contains(row)));
+        }
+    }
+
+    #[test]
+    fn statistics_round_trip() {
+        let mut statistics = Statistics::default();
+        statistics.record(1, true, Some(Duration::from_millis(41_500)));
+        statistics.record(1, false, None);
+        statistics.record(CUSTOM_DIFFICULTY, false, None);
+        let loaded = Statistics::from_toml(&statistics.to_toml().unwrap()).unwrap();
+        for (record, saved) in loaded.records.iter().zip(statistics.records) {
+            assert_eq!((record.played, record.won, record.best_time), (saved.played, saved.won, saved.best_time));
+        }
+    }
+
+    // Records for boards that aren't around any more are left out, and ones that weren't saved start empty
+    #[test]
+    fn statistics_go_by_name() {
+        let statistics = Statistics::from_toml("[Expert]\nplayed = 4\nwon = 2\n\n[Gone]\nplayed = 9\n").unwrap();
+        assert_eq!((statistics.records[2].played, statistics.records[2].won), (4, 2));
+        assert_eq!(statistics.records.iter().map(|record| record.played).sum::<u32>(), 4);
+    }
+
+    #[test]
+    fn rows_do_what_they_say() {
+        let mut game = Game::new(Field::empty(9, 9, 10));
+        game.set_scene(Scene::Menu);
+        game.config.lives = 0;
+        game.activate_menu_row(row_index(&game, Row::Menu(MenuRow::Lives)), 1);
+        assert_eq!(game.config.lives, 1);
+
+        game.set_scene(Scene::CustomBoard);
+        game.custom.width = CUSTOM_MIN_SIZE;
+        game.activate_menu_row(row_index(&game, Row::CustomBoard(CustomBoardRow::Width)), 1);
+        assert_eq!(game.custom.width, CUSTOM_MIN_SIZE + 1);
+
+        game.set_scene(Scene::Settings);
+        let animations = game.config.animations;
+        game.activate_menu_row(row_index(&game, Row::Settings(SettingsRow::Animations)), 0);
+        assert_eq!(game.config.animations, !animations);
+
+        game.set_scene(Scene::Bindings);
+        game.activate_menu_row(row_index(&game, Row::Bindings(BindingsRow::Action(Action::Flag))), 0);
+        assert!(game.rebinding == Some(Action::Flag));
+    }
+}
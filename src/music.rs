@@ -0,0 +1,215 @@
+use std::fs;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Instant;
+
+use rand::seq::SliceRandom;
+use sdl2::mixer::{Music, MAX_VOLUME};
+use serde::{Deserialize, Serialize};
+
+use crate::config::Config;
+use crate::menu::Scene;
+use crate::Game;
+
+const AWAKE10_MEGA_WALL: &[u8; 2026231] = include_bytes!("../assets/awake10_megaWall.mp3");
+
+const EXTENSIONS: [&str; 3] = ["ogg", "mp3", "flac"];
+
+// Seconds to fade all the way in or out
+const FADE_TIME: f32 = 1.5;
+// How loud the music is behind the pause menu
+const PAUSED_LEVEL: f32 = 0.4;
+
+// Set by SDL_mixer when a track ends, it can only call a plain function
+static TRACK_FINISHED: AtomicBool = AtomicBool::new(false);
+
+fn track_finished() {
+    TRACK_FINISHED.store(true, Ordering::SeqCst);
+}
+
+#[derive(Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+pub enum Repeat {
+    // Start the playlist over once it ends
+    #[default]
+    All,
+    One,
+    Off,
+}
+
+pub const REPEATS: [Repeat; 3] = [Repeat::All, Repeat::One, Repeat::Off];
+
+impl Repeat {
+    pub fn name(self) -> &'static str {
+        match self {
+            Repeat::All => "All",
+            Repeat::One => "One",
+            Repeat::Off => "Off",
+        }
+    }
+}
+
+pub struct MusicPlayer {
+    // Played when there's no playlist, or a track won't load
+    embedded: Music<'static>,
+    playlist: Vec<PathBuf>,
+    // Indices into playlist in the order they're played
+    order: Vec<usize>,
+    position: usize,
+    // Kept alive for as long as it plays
+    track: Option<Music<'static>>,
+    // Reached the end of the playlist with repeat off
+    stopped: bool,
+    // Fade level from 0 to 1, on top of the music volume
+    level: f32,
+    last_update: Instant,
+    last_scene: Scene,
+    // Settings the playlist was last set up for, so changes take effect straight away
+    last_shuffle: bool,
+    last_repeat: Repeat,
+}
+
+impl MusicPlayer {
+    pub fn new(config: &Config) -> MusicPlayer {
+        let embedded = Music::from_static_bytes(AWAKE10_MEGA_WALL).expect("Couldn't create music from static bytes");
+        Music::hook_finished(track_finished);
+        let mut music_player = MusicPlayer {
+            embedded,
+            playlist: playlist(),
+            order: Vec::new(),
+            position: 0,
+            track: None,
+            stopped: false,
+            level: 0.0,
+            last_update: Instant::now(),
+            last_scene: Scene::Menu,
+            last_shuffle: config.music_shuffle,
+            last_repeat: config.music_repeat,
+        };
+        music_player.shuffle(config.music_shuffle);
+        music_player.play();
+        music_player
+    }
+
+    fn shuffle(&mut self, shuffle: bool) {
+        self.order = (0..self.playlist.len()).collect();
+        if shuffle {
+            self.order.shuffle(&mut rand::thread_rng());
+        }
+        self.position = 0;
+    }
+
+    // Changes the order without interrupting the track that's playing, it stays where it is in the new order
+    fn reorder(&mut self, shuffle: bool) {
+        let current = self.order.get(self.position).copied();
+        self.shuffle(shuffle);
+        if let Some(current) = current {
+            self.position = self.order.iter().position(|track| *track == current).unwrap_or(0);
+            // Shuffled, the rest of the playlist comes after it
+            if shuffle {
+                self.order.swap(0, self.position);
+                self.position = 0;
+            }
+        }
+    }
+
+    fn play(&mut self) {
+        self.track = self.order.get(self.position)
+            .and_then(|track| match Music::from_file(&self.playlist[*track]) {
+                Ok(music) => Some(music),
+                Err(e) => {
+                    eprintln!("Couldn't load {}: {}", self.playlist[*track].display(), e);
+                    None
+                },
+            });
+        // The embedded track loops forever, tracks are moved on from, or started again on repeat one, when they finish
+        let (music, loops) = match &self.track {
+            Some(track) => (track, 0),
+            None => (&self.embedded, -1),
+        };
+        self.level = 0.0;
+        Music::set_volume(0);
+        let _ = music.play(loops);
+        TRACK_FINISHED.store(false, Ordering::SeqCst);
+        self.stopped = false;
+    }
+
+    pub fn next(&mut self, config: &Config) {
+        if self.playlist.is_empty() {
+            return;
+        }
+        self.position += 1;
+        if self.position >= self.order.len() {
+            if config.music_repeat == Repeat::Off {
+                self.stopped = true;
+                Music::halt();
+                TRACK_FINISHED.store(false, Ordering::SeqCst);
+                return;
+            }
+            self.shuffle(config.music_shuffle);
+        }
+        self.play();
+    }
+
+    // Called every frame, follows the settings and fades between scenes
+    pub fn update(&mut self, game: &mut Game) {
+        if game.skip_track {
+            game.skip_track = false;
+            self.next(&game.config);
+        } else if TRACK_FINISHED.swap(false, Ordering::SeqCst) && !self.stopped {
+            if game.config.music_repeat == Repeat::One {
+                self.play();
+            } else {
+                self.next(&game.config);
+            }
+        }
+        if game.config.music_shuffle != self.last_shuffle {
+            self.last_shuffle = game.config.music_shuffle;
+            self.reorder(self.last_shuffle);
+        }
+        if game.config.music_repeat != self.last_repeat {
+            self.last_repeat = game.config.music_repeat;
+            // Turning repeat back on after the playlist ran out starts it over
+            if self.stopped && self.last_repeat != Repeat::Off {
+                self.shuffle(game.config.music_shuffle);
+                self.play();
+            }
+        }
+        // A new game after the end screen gets a new track
+        if self.last_scene == Scene::End && game.scene == Scene::Playing {
+            self.next(&game.config);
+        }
+        self.last_scene = game.scene;
+
+        let target = match game.scene {
+            Scene::Paused => PAUSED_LEVEL,
+            Scene::End => 0.0,
+            _ => 1.0,
+        };
+        let elapsed = self.last_update.elapsed().as_secs_f32();
+        self.last_update = Instant::now();
+        let step = elapsed / FADE_TIME;
+        self.level = if self.level < target { (self.level + step).min(target) } else { (self.level - step).max(target) };
+
+        let volume = if game.config.muted { 0.0 } else { self.level * f32::from(game.config.music_volume) / 100.0 };
+        Music::set_volume((volume * MAX_VOLUME as f32) as i32);
+        if !game.config.music {
+            Music::pause();
+        } else if Music::is_paused() {
+            Music::resume();
+        }
+    }
+}
+
+// Every track in the music directory next to the config file, in name order
+fn playlist() -> Vec<PathBuf> {
+    let Some(entries) = Config::directory().and_then(|directory| fs::read_dir(directory.join("music")).ok()) else {
+        return Vec::new();
+    };
+    let mut playlist: Vec<PathBuf> = entries
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| path.extension().and_then(|extension| extension.to_str()).is_some_and(|extension| EXTENSIONS.iter().any(|supported| extension.eq_ignore_ascii_case(supported))))
+        .collect();
+    playlist.sort();
+    playlist
+}
@@ -0,0 +1,744 @@
+use std::io::{self, BufReader, Read, Write};
+use std::net::{Shutdown, SocketAddr, TcpListener, TcpStream};
+use std::sync::mpsc::{self, Receiver, Sender, TryRecvError};
+use std::thread;
+use std::time::Duration;
+
+use crate::field::{Cell, Field, Rules};
+use crate::Game;
+
+// What a client is allowed to know about a cell, mines stay on the server until they explode
+#[derive(Clone, Copy, PartialEq)]
+pub enum CellState {
+    Hidden,
+    Flagged,
+    Revealed(u8),
+    Exploded,
+}
+
+impl CellState {
+    pub fn of(cell: &Cell) -> CellState {
+        if cell.revealed {
+            if cell.has_mine() {
+                CellState::Exploded
+            } else {
+                CellState::Revealed(cell.mines_around)
+            }
+        } else if cell.flagged() {
+            CellState::Flagged
+        } else {
+            CellState::Hidden
+        }
+    }
+
+    pub fn apply(self, cell: &mut Cell) {
+        *cell = Cell::new();
+        match self {
+            CellState::Hidden => {},
+            CellState::Flagged => cell.flags = 1,
+            CellState::Revealed(mines_around) => {
+                cell.revealed = true;
+                cell.mines_around = mines_around;
+            },
+            CellState::Exploded => {
+                cell.revealed = true;
+                cell.mines = 1;
+            },
+        }
+    }
+
+    fn to_byte(self) -> u8 {
+        match self {
+            CellState::Hidden => 0,
+            CellState::Flagged => 1,
+            CellState::Exploded => 2,
+            CellState::Revealed(mines_around) => 3 + mines_around,
+        }
+    }
+
+    fn from_byte(byte: u8) -> CellState {
+        match byte {
+            0 => CellState::Hidden,
+            1 => CellState::Flagged,
+            2 => CellState::Exploded,
+            _ => CellState::Revealed(byte - 3),
+        }
+    }
+}
+
+pub enum ClientMessage {
+    Cursor(u32),
+    Reveal(u32),
+    Flag(u32),
+    NewGame,
+    Chord(u32),
+}
+
+impl ClientMessage {
+    pub fn write(&self, writer: &mut impl Write) -> io::Result<()> {
+        let mut bytes = Vec::<u8>::with_capacity(5);
+        match self {
+            ClientMessage::Cursor(index) => {
+                bytes.push(0);
+                bytes.extend_from_slice(&index.to_le_bytes());
+            },
+            ClientMessage::Reveal(index) => {
+                bytes.push(1);
+                bytes.extend_from_slice(&index.to_le_bytes());
+            },
+            ClientMessage::Flag(index) => {
+                bytes.push(2);
+                bytes.extend_from_slice(&index.to_le_bytes());
+            },
+            ClientMessage::NewGame => bytes.push(3),
+            ClientMessage::Chord(index) => {
+                bytes.push(4);
+                bytes.extend_from_slice(&index.to_le_bytes());
+            },
+        }
+        writer.write_all(&bytes)
+    }
+
+    pub fn read(reader: &mut impl Read) -> io::Result<ClientMessage> {
+        match read_u8(reader)? {
+            0 => Ok(ClientMessage::Cursor(read_u32(reader)?)),
+            1 => Ok(ClientMessage::Reveal(read_u32(reader)?)),
+            2 => Ok(ClientMessage::Flag(read_u32(reader)?)),
+            3 => Ok(ClientMessage::NewGame),
+            4 => Ok(ClientMessage::Chord(read_u32(reader)?)),
+            tag => Err(invalid_data(format!("Unknown client message {}", tag))),
+        }
+    }
+}
+
+pub enum ServerMessage {
+    Welcome { player_id: u8, width: u32, height: u32, mines: u32 },
+    Reset { width: u32, height: u32, mines: u32 },
+    Cells(Vec<(u32, CellState)>),
+    Status { flags_left: u32, lives: u32 },
+    Cursor { player_id: u8, index: u32 },
+    Left { player_id: u8 },
+    GameOver { was_winner: bool },
+}
+
+impl ServerMessage {
+    pub fn write(&self, writer: &mut impl Write) -> io::Result<()> {
+        let mut bytes = Vec::<u8>::new();
+        match self {
+            ServerMessage::Welcome { player_id, width, height, mines } => {
+                bytes.push(0);
+                bytes.push(*player_id);
+                bytes.extend_from_slice(&width.to_le_bytes());
+                bytes.extend_from_slice(&height.to_le_bytes());
+                bytes.extend_from_slice(&mines.to_le_bytes());
+            },
+            ServerMessage::Reset { width, height, mines } => {
+                bytes.push(1);
+                bytes.extend_from_slice(&width.to_le_bytes());
+                bytes.extend_from_slice(&height.to_le_bytes());
+                bytes.extend_from_slice(&mines.to_le_bytes());
+            },
+            ServerMessage::Cells(cells) => {
+                bytes.push(2);
+                bytes.extend_from_slice(&(cells.len() as u32).to_le_bytes());
+                for (index, state) in cells {
+                    bytes.extend_from_slice(&index.to_le_bytes());
+                    bytes.push(state.to_byte());
+                }
+            },
+            ServerMessage::Status { flags_left, lives } => {
+                bytes.push(3);
+                bytes.extend_from_slice(&flags_left.to_le_bytes());
+                bytes.extend_from_slice(&lives.to_le_bytes());
+            },
+            ServerMessage::Cursor { player_id, index } => {
+                bytes.push(4);
+                bytes.push(*player_id);
+                bytes.extend_from_slice(&index.to_le_bytes());
+            },
+            ServerMessage::Left { player_id } => {
+                bytes.push(5);
+                bytes.push(*player_id);
+            },
+            ServerMessage::GameOver { was_winner } => {
+                bytes.push(6);
+                bytes.push(*was_winner as u8);
+            },
+        }
+        writer.write_all(&bytes)
+    }
+
+    pub fn read(reader: &mut impl Read) -> io::Result<ServerMessage> {
+        match read_u8(reader)? {
+            0 => {
+                let player_id = read_u8(reader)?;
+                let (width, height, mines) = read_board(reader)?;
+                Ok(ServerMessage::Welcome { player_id, width, height, mines })
+            },
+            1 => {
+                let (width, height, mines) = read_board(reader)?;
+                Ok(ServerMessage::Reset { width, height, mines })
+            },
+            2 => {
+                let count = read_u32(reader)?;
+                let mut cells = Vec::with_capacity(count.min(u16::MAX as u32) as usize);
+                for _ in 0..count {
+                    cells.push((read_u32(reader)?, CellState::from_byte(read_u8(reader)?)));
+                }
+                Ok(ServerMessage::Cells(cells))
+            },
+            3 => Ok(ServerMessage::Status {
+                flags_left: read_u32(reader)?,
+                lives: read_u32(reader)?,
+            }),
+            4 => Ok(ServerMessage::Cursor {
+                player_id: read_u8(reader)?,
+                index: read_u32(reader)?,
+            }),
+            5 => Ok(ServerMessage::Left { player_id: read_u8(reader)? }),
+            6 => Ok(ServerMessage::GameOver { was_winner: read_u8(reader)? != 0 }),
+            tag => Err(invalid_data(format!("Unknown server message {}", tag))),
+        }
+    }
+}
+
+fn read_u8(reader: &mut impl Read) -> io::Result<u8> {
+    let mut bytes = [0; 1];
+    reader.read_exact(&mut bytes)?;
+    Ok(bytes[0])
+}
+
+fn read_u32(reader: &mut impl Read) -> io::Result<u32> {
+    let mut bytes = [0; 4];
+    reader.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+// Width, height and mines, checked before the client makes a field from them
+fn read_board(reader: &mut impl Read) -> io::Result<(u32, u32, u32)> {
+    let (width, height, mines) = (read_u32(reader)?, read_u32(reader)?, read_u32(reader)?);
+    let sizes = MIN_BOARD_SIZE as u32..=MAX_BOARD_SIZE as u32;
+    if !sizes.contains(&width) || !sizes.contains(&height) || mines > width * height {
+        return Err(invalid_data(format!("Board of {}x{} with {} mines is out of range", width, height, mines)));
+    }
+    Ok((width, height, mines))
+}
+
+// Boards the server will play and clients will accept, the same smallest size --size allows
+pub const MIN_BOARD_SIZE: usize = 2;
+pub const MAX_BOARD_SIZE: usize = 200;
+
+// A client that can't take a message for this long is dropped
+const WRITE_TIMEOUT: Duration = Duration::from_secs(5);
+
+fn invalid_data(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+#[derive(Clone, Copy)]
+pub struct ServerOptions {
+    pub width: usize,
+    pub height: usize,
+    pub mines: u32,
+    // 0 means the first mine hit ends the game for everyone
+    pub lives: u32,
+}
+
+impl ServerOptions {
+    // What the command line asked for, cut down to a board the server can place and clients will accept
+    fn checked(self) -> ServerOptions {
+        let width = self.width.clamp(MIN_BOARD_SIZE, MAX_BOARD_SIZE);
+        let height = self.height.clamp(MIN_BOARD_SIZE, MAX_BOARD_SIZE);
+        ServerOptions {
+            width,
+            height,
+            mines: self.mines.min(Field::max_mines(width, height)),
+            ..self
+        }
+    }
+}
+
+enum ServerEvent {
+    Joined(TcpStream),
+    Message(u8, ClientMessage),
+    Left(u8),
+}
+
+struct Player {
+    id: u8,
+    // Encoded messages for the player's writer thread
+    outgoing: Sender<Vec<u8>>,
+    cursor: u32,
+}
+
+impl Player {
+    fn send(&self, message: &ServerMessage) {
+        let mut bytes = Vec::<u8>::new();
+        let _ = message.write(&mut bytes);
+        let _ = self.outgoing.send(bytes);
+    }
+}
+
+// Each player is written to on their own thread, so one slow player can't hold up the game for everyone
+fn spawn_writer(mut stream: TcpStream) -> Sender<Vec<u8>> {
+    let (sender, receiver) = mpsc::channel::<Vec<u8>>();
+    thread::spawn(move || {
+        let _ = stream.set_write_timeout(Some(WRITE_TIMEOUT));
+        for bytes in receiver {
+            if stream.write_all(&bytes).is_err() {
+                // Also ends the reader, which lets the server know the player left
+                let _ = stream.shutdown(Shutdown::Both);
+                return;
+            }
+        }
+    });
+    sender
+}
+
+struct Server {
+    options: ServerOptions,
+    game: Game,
+    lives: u32,
+    game_over: Option<bool>,
+    players: Vec<Player>,
+    events: Sender<ServerEvent>,
+}
+
+// Runs the server on the current thread, used for dedicated servers
+pub fn serve(address: &str, options: ServerOptions) -> io::Result<()> {
+    let listener = TcpListener::bind(address)?;
+    println!("Serving on {}", listener.local_addr()?);
+    run_server(listener, options);
+    Ok(())
+}
+
+// Runs the server on a background thread and returns the address it is listening on
+pub fn spawn_server(address: &str, options: ServerOptions) -> io::Result<SocketAddr> {
+    let listener = TcpListener::bind(address)?;
+    let local_address = listener.local_addr()?;
+    thread::spawn(move || run_server(listener, options));
+    Ok(local_address)
+}
+
+// Nothing is drawn on the server, so its game keeps no animations
+fn server_game(options: ServerOptions) -> Game {
+    let mut game = Game::new(Field::new(options.width, options.height, options.mines, Rules::default()));
+    game.config.animations = false;
+    game
+}
+
+fn run_server(listener: TcpListener, options: ServerOptions) {
+    let options = options.checked();
+    let (sender, receiver) = mpsc::channel();
+    let accept_sender = sender.clone();
+    thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            if accept_sender.send(ServerEvent::Joined(stream)).is_err() {
+                break;
+            }
+        }
+    });
+
+    let mut server = Server {
+        options,
+        game: server_game(options),
+        lives: options.lives,
+        game_over: None,
+        players: Vec::new(),
+        events: sender,
+    };
+    for event in receiver {
+        match event {
+            ServerEvent::Joined(stream) => server.join(stream),
+            ServerEvent::Message(player_id, message) => server.handle(player_id, message),
+            ServerEvent::Left(player_id) => server.leave(player_id),
+        }
+    }
+}
+
+impl Server {
+    fn join(&mut self, stream: TcpStream) {
+        let Some(player_id) = (0..=u8::MAX).find(|id| self.players.iter().all(|player| player.id != *id)) else {
+            return;
+        };
+        let _ = stream.set_nodelay(true);
+        let Ok(reader) = stream.try_clone() else {
+            return;
+        };
+        let events = self.events.clone();
+        thread::spawn(move || {
+            let mut reader = BufReader::new(reader);
+            while let Ok(message) = ClientMessage::read(&mut reader) {
+                if events.send(ServerEvent::Message(player_id, message)).is_err() {
+                    return;
+                }
+            }
+            let _ = events.send(ServerEvent::Left(player_id));
+        });
+
+        let player = Player {
+            id: player_id,
+            outgoing: spawn_writer(stream),
+            cursor: 0,
+        };
+        let mut messages = vec![
+            ServerMessage::Welcome {
+                player_id,
+                width: self.game.field.width as u32,
+                height: self.game.field.height as u32,
+                mines: self.game.field.mines,
+            },
+            self.all_cells(),
+            self.status(),
+        ];
+        for other in &self.players {
+            messages.push(ServerMessage::Cursor { player_id: other.id, index: other.cursor });
+        }
+        if let Some(was_winner) = self.game_over {
+            messages.push(ServerMessage::GameOver { was_winner });
+        }
+        for message in messages {
+            player.send(&message);
+        }
+        self.players.push(player);
+        self.broadcast_except(player_id, &ServerMessage::Cursor { player_id, index: 0 });
+    }
+
+    fn leave(&mut self, player_id: u8) {
+        self.players.retain(|player| player.id != player_id);
+        self.broadcast(&ServerMessage::Left { player_id });
+    }
+
+    fn handle(&mut self, player_id: u8, message: ClientMessage) {
+        match message {
+            ClientMessage::Cursor(index) => {
+                if index as usize >= self.game.field.cells.len() {
+                    return;
+                }
+                if let Some(player) = self.players.iter_mut().find(|player| player.id == player_id) {
+                    player.cursor = index;
+                }
+                self.broadcast_except(player_id, &ServerMessage::Cursor { player_id, index });
+            },
+            ClientMessage::Reveal(index) | ClientMessage::Flag(index) | ClientMessage::Chord(index) => {
+                let index = index as usize;
+                if self.game_over.is_some() || index >= self.game.field.cells.len() {
+                    return;
+                }
+                let before: Vec<CellState> = self.game.field.cells.iter().map(CellState::of).collect();
+                match message {
+                    ClientMessage::Reveal(_) => self.game.reveal_from_index(index),
+                    ClientMessage::Flag(_) => self.game.flag_from_index(index),
+                    _ => self.game.chord_from_index(index),
+                }
+                // Nothing plays the server's sounds, they'd only pile up
+                self.game.sounds.clear();
+                if self.game.should_die {
+                    self.game.should_die = false;
+                    if self.game.was_winner {
+                        self.game_over = Some(true);
+                    } else {
+                        let mut mines = std::mem::take(&mut self.game.hit_mines);
+                        if mines.is_empty() {
+                            mines.push(index);
+                        }
+                        // Each mine a chord set off costs its own life
+                        for mine in mines {
+                            self.explode(mine);
+                        }
+                    }
+                }
+
+                let cells: Vec<(u32, CellState)> = self.game.field.cells.iter()
+                    .map(CellState::of)
+                    .zip(before)
+                    .enumerate()
+                    .filter(|(_, (after, before))| after != before)
+                    .map(|(index, (after, _))| (index as u32, after))
+                    .collect();
+                if !cells.is_empty() {
+                    self.broadcast(&ServerMessage::Cells(cells));
+                }
+                self.broadcast(&self.status());
+                if let Some(was_winner) = self.game_over {
+                    self.broadcast(&ServerMessage::GameOver { was_winner });
+                }
+            },
+            ClientMessage::NewGame => {
+                if self.game_over.is_none() {
+                    return;
+                }
+                self.game = server_game(self.options);
+                self.lives = self.options.lives;
+                self.game_over = None;
+                self.broadcast(&ServerMessage::Reset {
+                    width: self.game.field.width as u32,
+                    height: self.game.field.height as u32,
+                    mines: self.game.field.mines,
+                });
+                self.broadcast(&self.status());
+            },
+        }
+    }
+
+    // Someone hit a mine, either spend a shared life or end the game for everyone
+    fn explode(&mut self, index: usize) {
+        match self.game.field.explode(index, &mut self.lives) {
+            Some(true) => self.game_over = Some(true),
+            Some(false) => {},
+            None => {
+                // Shown to everyone as the mine that ended the game
+                self.game.field.cells[index].revealed = true;
+                self.game_over = Some(false);
+            },
+        }
+    }
+
+    fn all_cells(&self) -> ServerMessage {
+        ServerMessage::Cells(self.game.field.cells.iter()
+            .map(CellState::of)
+            .enumerate()
+            .filter(|(_, state)| *state != CellState::Hidden)
+            .map(|(index, state)| (index as u32, state))
+            .collect())
+    }
+
+    fn status(&self) -> ServerMessage {
+        ServerMessage::Status {
+            flags_left: self.game.field.flags_left,
+            lives: self.lives,
+        }
+    }
+
+    fn broadcast(&mut self, message: &ServerMessage) {
+        for player in &self.players {
+            player.send(message);
+        }
+    }
+
+    fn broadcast_except(&mut self, player_id: u8, message: &ServerMessage) {
+        for player in self.players.iter().filter(|player| player.id != player_id) {
+            player.send(message);
+        }
+    }
+}
+
+pub struct Client {
+    pub player_id: u8,
+    // Cursors of the other players
+    pub cursors: Vec<(u8, usize)>,
+    stream: TcpStream,
+    messages: Receiver<ServerMessage>,
+    last_cursor: Option<usize>,
+}
+
+impl Client {
+    // Blocks until the server has welcomed us and returns the field to mirror
+    pub fn connect(address: &str) -> io::Result<(Client, Field)> {
+        let stream = TcpStream::connect(address)?;
+        stream.set_nodelay(true)?;
+        let mut reader = BufReader::new(stream.try_clone()?);
+        let (player_id, field) = match ServerMessage::read(&mut reader)? {
+            ServerMessage::Welcome { player_id, width, height, mines } => (player_id, Field::empty(width as usize, height as usize, mines)),
+            _ => return Err(invalid_data("Expected a welcome from the server".to_string())),
+        };
+
+        let (sender, messages) = mpsc::channel();
+        thread::spawn(move || {
+            while let Ok(message) = ServerMessage::read(&mut reader) {
+                if sender.send(message).is_err() {
+                    return;
+                }
+            }
+        });
+
+        Ok((Client {
+            player_id,
+            cursors: Vec::new(),
+            stream,
+            messages,
+            last_cursor: None,
+        }, field))
+    }
+
+    pub fn send(&mut self, message: ClientMessage) {
+        let _ = message.write(&mut self.stream);
+    }
+
+    pub fn send_cursor(&mut self, index: usize) {
+        if self.last_cursor != Some(index) {
+            self.last_cursor = Some(index);
+            self.send(ClientMessage::Cursor(index as u32));
+        }
+    }
+
+    pub fn poll(&mut self) -> io::Result<Vec<ServerMessage>> {
+        let mut messages = Vec::new();
+        loop {
+            match self.messages.try_recv() {
+                Ok(message) => messages.push(message),
+                Err(TryRecvError::Empty) => return Ok(messages),
+                Err(TryRecvError::Disconnected) if !messages.is_empty() => return Ok(messages),
+                Err(TryRecvError::Disconnected) => return Err(io::Error::new(io::ErrorKind::ConnectionAborted, "Lost connection to the server")),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn client_messages() -> Vec<ClientMessage> {
+        vec![ClientMessage::Cursor(7), ClientMessage::Reveal(u32::MAX), ClientMessage::Flag(0), ClientMessage::NewGame, ClientMessage::Chord(123_456)]
+    }
+
+    fn server_messages() -> Vec<ServerMessage> {
+        vec![
+            ServerMessage::Welcome { player_id: 3, width: 40, height: 24, mines: 180 },
+            ServerMessage::Reset { width: 9, height: 9, mines: 10 },
+            ServerMessage::Cells(vec![(0, CellState::Hidden), (1, CellState::Flagged), (2, CellState::Revealed(0)), (3, CellState::Revealed(8)), (4, CellState::Exploded)]),
+            ServerMessage::Cells(Vec::new()),
+            ServerMessage::Status { flags_left: 17, lives: 2 },
+            ServerMessage::Cursor { player_id: 255, index: 42 },
+            ServerMessage::Left { player_id: 1 },
+            ServerMessage::GameOver { was_winner: true },
+            ServerMessage::GameOver { was_winner: false },
+        ]
+    }
+
+    fn client_bytes(message: &ClientMessage) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        message.write(&mut bytes).expect("Couldn't write client message");
+        bytes
+    }
+
+    fn server_bytes(message: &ServerMessage) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        message.write(&mut bytes).expect("Couldn't write server message");
+        bytes
+    }
+
+    // Read back and written again, a message comes out as the same bytes, using up all of them
+    #[test]
+    fn client_messages_round_trip() {
+        for message in client_messages() {
+            let bytes = client_bytes(&message);
+            let mut reader = &bytes[..];
+            let read = ClientMessage::read(&mut reader).expect("Couldn't read client message");
+            assert!(reader.is_empty());
+            assert_eq!(client_bytes(&read), bytes);
+        }
+    }
+
+    #[test]
+    fn server_messages_round_trip() {
+        for message in server_messages() {
+            let bytes = server_bytes(&message);
+            let mut reader = &bytes[..];
+            let read = ServerMessage::read(&mut reader).expect("Couldn't read server message");
+            assert!(reader.is_empty());
+            assert_eq!(server_bytes(&read), bytes);
+        }
+    }
+
+    #[test]
+    fn cell_states_round_trip() {
+        let mut states = vec![CellState::Hidden, CellState::Flagged, CellState::Exploded];
+        states.extend((0..=26).map(CellState::Revealed));
+        for state in states {
+            assert!(CellState::from_byte(state.to_byte()) == state);
+            let mut cell = Cell::new();
+            state.apply(&mut cell);
+            assert!(CellState::of(&cell) == state);
+        }
+    }
+
+    #[test]
+    fn unknown_tags_are_errors() {
+        assert!(ClientMessage::read(&mut &[5u8][..]).is_err());
+        assert!(ClientMessage::read(&mut &[255u8][..]).is_err());
+        assert!(ServerMessage::read(&mut &[7u8][..]).is_err());
+        assert!(ServerMessage::read(&mut &[255u8][..]).is_err());
+    }
+
+    #[test]
+    fn truncated_messages_are_errors() {
+        for message in client_messages() {
+            let bytes = client_bytes(&message);
+            for length in 0..bytes.len() {
+                assert!(ClientMessage::read(&mut &bytes[..length]).is_err());
+            }
+        }
+        for message in server_messages() {
+            let bytes = server_bytes(&message);
+            for length in 0..bytes.len() {
+                assert!(ServerMessage::read(&mut &bytes[..length]).is_err());
+            }
+        }
+    }
+
+    #[test]
+    fn boards_out_of_range_are_errors() {
+        let boards = [(0, 9, 0), (9, 0, 0), (1, 9, 0), (9, MAX_BOARD_SIZE as u32 + 1, 10), (u32::MAX, u32::MAX, 1), (9, 9, 82)];
+        for (width, height, mines) in boards {
+            for message in [ServerMessage::Welcome { player_id: 0, width, height, mines }, ServerMessage::Reset { width, height, mines }] {
+                assert!(ServerMessage::read(&mut server_bytes(&message).as_slice()).is_err());
+            }
+        }
+        let board = ServerMessage::Reset { width: MAX_BOARD_SIZE as u32, height: MIN_BOARD_SIZE as u32, mines: 2 * MAX_BOARD_SIZE as u32 };
+        assert!(ServerMessage::read(&mut server_bytes(&board).as_slice()).is_ok());
+    }
+
+    #[test]
+    fn server_checks_its_options() {
+        let options = ServerOptions { width: 0, height: 100_000, mines: u32::MAX, lives: 0 }.checked();
+        assert_eq!((options.width, options.height), (MIN_BOARD_SIZE, MAX_BOARD_SIZE));
+        assert_eq!(options.mines, Field::max_mines(options.width, options.height));
+    }
+
+    // Used to hang the server while placing mines
+    #[test]
+    fn server_fills_dense_boards() {
+        for _ in 0..100 {
+            let game = server_game(ServerOptions { width: 5, height: 6, mines: 10, lives: 0 }.checked());
+            assert_eq!(game.field.cells.iter().filter(|cell| cell.has_mine()).count(), 10);
+        }
+    }
+
+    fn server(lives: u32) -> Server {
+        let options = ServerOptions { width: 3, height: 1, mines: 2, lives };
+        let mut server = Server {
+            options,
+            game: server_game(options),
+            lives,
+            game_over: None,
+            players: Vec::new(),
+            events: mpsc::channel().0,
+        };
+        let field = &mut server.game.field;
+        for (cell, mines) in field.cells.iter_mut().zip([1, 0, 1]) {
+            *cell = Cell::new();
+            cell.mines = mines;
+        }
+        server
+    }
+
+    #[test]
+    fn server_spends_shared_lives() {
+        let mut server = server(1);
+        server.explode(0);
+        assert_eq!((server.lives, server.game.field.flags_left, server.game_over), (0, 1, None));
+        server.explode(2);
+        assert_eq!(server.game_over, Some(false));
+        assert!(server.game.field.cells[2].revealed);
+    }
+
+    #[test]
+    fn server_wins_on_an_exploded_last_mine() {
+        let mut server = server(1);
+        server.game.field.cycle_flag(0, false);
+        server.explode(2);
+        assert_eq!(server.game_over, Some(true));
+    }
+}
@@ -0,0 +1,107 @@
+use sdl2::pixels::Color;
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+pub enum Palette {
+    #[default]
+    Standard,
+    Deuteranopia,
+    Protanopia,
+    Tritanopia,
+    HighContrast,
+}
+
+pub const PALETTES: [Palette; 5] = [Palette::Standard, Palette::Deuteranopia, Palette::Protanopia, Palette::Tritanopia, Palette::HighContrast];
+
+pub struct Colors {
+    // HUD, menus and the end screen
+    pub background: Color,
+    pub text: Color,
+    pub selection: Color,
+    pub highlight: Color,
+    // Numbers drawn over the theme's tiles, None keeps the theme's own number tiles
+    pub numbers: Option<[Color; 8]>,
+    // Fill for revealed cells instead of the theme's tile
+    pub revealed: Option<Color>,
+    // Alternating colours around flags and the cursor
+    pub outline: Option<(Color, Color)>,
+}
+
+impl Palette {
+    pub fn name(self) -> &'static str {
+        match self {
+            Palette::Standard => "Standard",
+            Palette::Deuteranopia => "Deuteranopia",
+            Palette::Protanopia => "Protanopia",
+            Palette::Tritanopia => "Tritanopia",
+            Palette::HighContrast => "High Contrast",
+        }
+    }
+
+    pub fn colors(self) -> Colors {
+        let black = Color::RGB(0, 0, 0);
+        let white = Color::RGB(255, 255, 255);
+        let grey = Color::RGB(128, 128, 128);
+        let light_grey = Color::RGB(160, 160, 160);
+        match self {
+            Palette::Standard => Colors {
+                background: grey,
+                text: black,
+                selection: light_grey,
+                highlight: Color::RGB(255, 0, 0),
+                numbers: None,
+                revealed: None,
+                outline: None,
+            },
+            // Blues against oranges, no red against green
+            Palette::Deuteranopia => Colors {
+                background: grey,
+                text: black,
+                selection: light_grey,
+                highlight: Color::RGB(0, 60, 160),
+                numbers: Some([Color::RGB(0, 114, 178), Color::RGB(213, 94, 0), Color::RGB(0, 0, 0), Color::RGB(0, 40, 110), Color::RGB(120, 60, 0), Color::RGB(0, 150, 200), Color::RGB(70, 0, 90), Color::RGB(90, 90, 90)]),
+                revealed: None,
+                outline: Some((black, Color::RGB(230, 159, 0))),
+            },
+            // Like deuteranopia, but reds look dark so oranges lean towards yellow
+            Palette::Protanopia => Colors {
+                background: grey,
+                text: black,
+                selection: light_grey,
+                highlight: Color::RGB(0, 60, 160),
+                numbers: Some([Color::RGB(0, 90, 181), Color::RGB(180, 130, 0), Color::RGB(0, 0, 0), Color::RGB(0, 40, 110), Color::RGB(100, 80, 0), Color::RGB(0, 150, 200), Color::RGB(80, 0, 120), Color::RGB(90, 90, 90)]),
+                revealed: None,
+                outline: Some((black, Color::RGB(240, 228, 66))),
+            },
+            // Reds against teals, no blue against yellow
+            Palette::Tritanopia => Colors {
+                background: grey,
+                text: black,
+                selection: light_grey,
+                highlight: Color::RGB(170, 0, 0),
+                numbers: Some([Color::RGB(0, 120, 120), Color::RGB(200, 30, 30), Color::RGB(0, 0, 0), Color::RGB(0, 70, 70), Color::RGB(120, 0, 60), Color::RGB(0, 160, 150), Color::RGB(90, 0, 0), Color::RGB(90, 90, 90)]),
+                revealed: None,
+                outline: Some((black, Color::RGB(255, 100, 120))),
+            },
+            // Every number is the same colour, they differ by shape and weight alone
+            Palette::HighContrast => Colors {
+                background: black,
+                text: white,
+                selection: Color::RGB(64, 64, 64),
+                highlight: Color::RGB(255, 255, 0),
+                numbers: Some([white; 8]),
+                revealed: Some(black),
+                outline: Some((black, Color::RGB(255, 255, 0))),
+            },
+        }
+    }
+}
+
+// Pixels of extra thickness numbers are drawn with, so they differ by weight as well as by colour
+pub fn number_weight(number: u8) -> i32 {
+    match number {
+        1 | 2 => 1,
+        3..=5 => 2,
+        _ => 3,
+    }
+}
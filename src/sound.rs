@@ -0,0 +1,165 @@
+use sdl2::mixer::{Channel, Chunk, MAX_VOLUME};
+
+use crate::config::Config;
+use crate::synth::{self, semitones, Channel as SynthChannel, Envelope, Note, Synth, Waveform};
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Sound {
+    // With the number on the revealed cell, higher numbers sound higher
+    Reveal(u8),
+    Flag,
+    Unflag,
+    // A reveal that opened up a lot of the board
+    Cascade,
+    Explosion,
+    Victory,
+    // Moving around and picking rows in the menus
+    Navigate,
+}
+
+pub const SOUNDS: [Sound; 7] = [Sound::Reveal(0), Sound::Flag, Sound::Unflag, Sound::Cascade, Sound::Explosion, Sound::Victory, Sound::Navigate];
+
+impl Sound {
+    fn index(self) -> usize {
+        match self {
+            Sound::Reveal(_) => 0,
+            Sound::Flag => 1,
+            Sound::Unflag => 2,
+            Sound::Cascade => 3,
+            Sound::Explosion => 4,
+            Sound::Victory => 5,
+            Sound::Navigate => 6,
+        }
+    }
+
+    // sounds/<name>.wav next to the config file replaces the synthesized sound
+    fn file_name(self) -> &'static str {
+        match self {
+            Sound::Reveal(_) => "reveal.wav",
+            Sound::Flag => "flag.wav",
+            Sound::Unflag => "unflag.wav",
+            Sound::Cascade => "cascade.wav",
+            Sound::Explosion => "explosion.wav",
+            Sound::Victory => "victory.wav",
+            Sound::Navigate => "navigate.wav",
+        }
+    }
+
+    pub fn channels(self) -> Vec<SynthChannel> {
+        match self {
+            Sound::Reveal(mines_around) => vec![SynthChannel {
+                waveform: Waveform::Square(0.25),
+                envelope: Envelope::PLUCK,
+                notes: vec![Note::new(semitones(440.0, f32::from(mines_around) * 2.0), 0.06)],
+            }],
+            Sound::Flag => vec![SynthChannel {
+                waveform: Waveform::Square(0.5),
+                envelope: Envelope::FLAT,
+                notes: vec![Note::new(880.0, 0.05), Note::new(1_320.0, 0.07)],
+            }],
+            Sound::Unflag => vec![SynthChannel {
+                waveform: Waveform::Square(0.5),
+                envelope: Envelope::FLAT,
+                notes: vec![Note::new(1_320.0, 0.05), Note::new(880.0, 0.07)],
+            }],
+            Sound::Cascade => vec![
+                SynthChannel {
+                    waveform: Waveform::Triangle,
+                    envelope: Envelope::PLUCK,
+                    notes: vec![Note::slide(220.0, 880.0, 0.3)],
+                },
+                SynthChannel {
+                    waveform: Waveform::Noise,
+                    envelope: Envelope::BURST,
+                    notes: vec![Note { volume: 0.3, ..Note::new(8_000.0, 0.3) }],
+                },
+            ],
+            Sound::Explosion => vec![
+                SynthChannel {
+                    waveform: Waveform::Noise,
+                    envelope: Envelope { decay: 0.7, ..Envelope::BURST },
+                    notes: vec![Note::slide(4_000.0, 500.0, 0.7)],
+                },
+                SynthChannel {
+                    waveform: Waveform::Triangle,
+                    envelope: Envelope { decay: 0.5, ..Envelope::BURST },
+                    notes: vec![Note::slide(110.0, 40.0, 0.5)],
+                },
+            ],
+            Sound::Victory => vec![
+                SynthChannel {
+                    waveform: Waveform::Square(0.5),
+                    envelope: Envelope::PLUCK,
+                    notes: [523.0, 659.0, 784.0, 1_047.0].iter().map(|pitch| Note::new(*pitch, 0.12)).collect(),
+                },
+                SynthChannel {
+                    waveform: Waveform::Triangle,
+                    envelope: Envelope::FLAT,
+                    notes: vec![Note::new(262.0, 0.48)],
+                },
+            ],
+            Sound::Navigate => vec![SynthChannel {
+                waveform: Waveform::Square(0.125),
+                envelope: Envelope::FLAT,
+                notes: vec![Note::new(1_000.0, 0.015)],
+            }],
+        }
+    }
+}
+
+pub struct SoundEffects {
+    // Sounds replaced by a file, indexed by Sound::index
+    overrides: Vec<Option<Chunk>>,
+}
+
+impl SoundEffects {
+    // Needs the mixer to be open already
+    pub fn load() -> SoundEffects {
+        // Each replaced sound gets a mixer channel of its own, so a sound only ever cuts itself off
+        sdl2::mixer::allocate_channels(SOUNDS.len() as i32);
+        let sounds_directory = Config::directory().map(|directory| directory.join("sounds"));
+        let overrides = SOUNDS.iter()
+            .map(|sound| sounds_directory.as_ref().and_then(|directory| Chunk::from_file(directory.join(sound.file_name())).ok()))
+            .collect();
+        SoundEffects {
+            overrides,
+        }
+    }
+
+    pub fn play(&self, sound: Sound, volume: u8, synth: &mut Synth) {
+        match &self.overrides[sound.index()] {
+            Some(chunk) => {
+                let channel = Channel(sound.index() as i32);
+                channel.set_volume(i32::from(volume) * MAX_VOLUME / 100);
+                let _ = channel.play(chunk, 0);
+            },
+            None => synth.play(&synth::render(&sound.channels()), f32::from(volume) / 100.0),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Times the samples go from one side of 0 to the other, twice per cycle
+    fn crossings(samples: &[f32]) -> usize {
+        let signs: Vec<bool> = samples.iter().filter(|sample| **sample != 0.0).map(|sample| *sample > 0.0).collect();
+        signs.windows(2).filter(|pair| pair[0] != pair[1]).count()
+    }
+
+    #[test]
+    fn every_sound_stays_in_range() {
+        for sound in SOUNDS.into_iter().chain((1..=26).map(Sound::Reveal)) {
+            let samples = synth::render(&sound.channels());
+            assert!(!samples.is_empty());
+            assert!(samples.iter().all(|sample| (-1.0..=1.0).contains(sample)));
+        }
+    }
+
+    #[test]
+    fn reveals_get_higher_with_more_mines_around() {
+        let pitches: Vec<usize> = (0..=8).map(|mines_around| crossings(&synth::render(&Sound::Reveal(mines_around).channels()))).collect();
+        assert!(pitches.windows(2).all(|pair| pair[0] < pair[1]), "{:?}", pitches);
+    }
+}
@@ -0,0 +1,215 @@
+use std::mem::size_of;
+
+use sdl2::audio::AudioQueue;
+
+use crate::SAMPLE_RATE;
+
+#[derive(Clone, Copy)]
+pub enum Waveform {
+    // Fraction of each cycle spent high
+    Square(f32),
+    Triangle,
+    // Pitch is how often the noise changes
+    Noise,
+}
+
+// Times in seconds, sustain is a level from 0 to 1
+#[derive(Clone, Copy)]
+pub struct Envelope {
+    pub attack: f32,
+    pub decay: f32,
+    pub sustain: f32,
+    pub release: f32,
+}
+
+impl Envelope {
+    // Straight to full and dying away over the note
+    pub const PLUCK: Envelope = Envelope { attack: 0.002, decay: 0.08, sustain: 0.3, release: 0.04 };
+    pub const FLAT: Envelope = Envelope { attack: 0.005, decay: 0.0, sustain: 1.0, release: 0.01 };
+    pub const BURST: Envelope = Envelope { attack: 0.0, decay: 0.5, sustain: 0.0, release: 0.0 };
+
+    fn level(&self, time: f32, length: f32) -> f32 {
+        let level = if time < self.attack {
+            time / self.attack
+        } else if time < self.attack + self.decay {
+            1.0 - (1.0 - self.sustain) * (time - self.attack) / self.decay
+        } else {
+            self.sustain
+        };
+        // The release happens at the end of the note, not after it
+        let release_start = length - self.release;
+        if time > release_start && self.release > 0.0 {
+            level * (length - time) / self.release
+        } else {
+            level
+        }
+    }
+}
+
+// A pitch of 0 is a rest
+#[derive(Clone, Copy)]
+pub struct Note {
+    pub pitch: f32,
+    // Pitch at the end of the note, for slides
+    pub to: f32,
+    pub length: f32,
+    pub volume: f32,
+}
+
+impl Note {
+    pub fn new(pitch: f32, length: f32) -> Note {
+        Note { pitch, to: pitch, length, volume: 1.0 }
+    }
+
+    pub fn slide(pitch: f32, to: f32, length: f32) -> Note {
+        Note { pitch, to, length, volume: 1.0 }
+    }
+}
+
+// Plays its notes one after another
+pub struct Channel {
+    pub waveform: Waveform,
+    pub envelope: Envelope,
+    pub notes: Vec<Note>,
+}
+
+// Pitch of a semitone step above a pitch
+pub fn semitones(pitch: f32, steps: f32) -> f32 {
+    pitch * 2f32.powf(steps / 12.0)
+}
+
+// Renders the channels together at SAMPLE_RATE, samples are from -1 to 1
+pub fn render(channels: &[Channel]) -> Vec<f32> {
+    let mut mixed = Vec::<f32>::new();
+    for channel in channels {
+        let samples = render_channel(channel);
+        if samples.len() > mixed.len() {
+            mixed.resize(samples.len(), 0.0);
+        }
+        for (mixed, sample) in mixed.iter_mut().zip(samples) {
+            *mixed += sample;
+        }
+    }
+    for sample in &mut mixed {
+        *sample = sample.clamp(-1.0, 1.0);
+    }
+    mixed
+}
+
+fn render_channel(channel: &Channel) -> Vec<f32> {
+    let sample_rate = SAMPLE_RATE as f32;
+    let mut samples = Vec::new();
+    let mut phase: f32 = 0.0;
+    // 15 bit shift register like the NES noise channel, the same noise every time
+    let mut noise: u16 = 1;
+    for note in &channel.notes {
+        let length = (note.length * sample_rate) as usize;
+        for i in 0..length {
+            if note.pitch == 0.0 {
+                samples.push(0.0);
+                continue;
+            }
+            let time = i as f32 / sample_rate;
+            let pitch = note.pitch + (note.to - note.pitch) * i as f32 / length as f32;
+            let previous_phase = phase;
+            phase = (phase + pitch / sample_rate).fract();
+            let value = match channel.waveform {
+                Waveform::Square(duty) => if phase < duty { 1.0 } else { -1.0 },
+                Waveform::Triangle => 1.0 - 4.0 * (phase - 0.5).abs(),
+                Waveform::Noise => {
+                    if phase < previous_phase {
+                        let feedback = (noise ^ (noise >> 1)) & 1;
+                        noise = (noise >> 1) | (feedback << 14);
+                    }
+                    if noise & 1 == 1 { 1.0 } else { -1.0 }
+                },
+            };
+            samples.push(value * note.volume * channel.envelope.level(time, note.length) * 0.25);
+        }
+    }
+    samples
+}
+
+// Everything synthesized goes through one queue, new sounds are mixed into whatever hasn't played yet
+pub struct Synth {
+    queue: Option<AudioQueue<i16>>,
+    // What was last queued, the end of it is what's still waiting to play
+    pending: Vec<f32>,
+}
+
+impl Synth {
+    pub fn new(queue: Option<AudioQueue<i16>>) -> Synth {
+        Synth {
+            queue,
+            pending: Vec::new(),
+        }
+    }
+
+    pub fn play(&mut self, samples: &[f32], volume: f32) {
+        let Some(queue) = &self.queue else {
+            return;
+        };
+        let waiting = queue.size() as usize / size_of::<i16>();
+        self.pending.drain(..self.pending.len().saturating_sub(waiting));
+        if samples.len() > self.pending.len() {
+            self.pending.resize(samples.len(), 0.0);
+        }
+        for (pending, sample) in self.pending.iter_mut().zip(samples) {
+            *pending = (*pending + sample * volume).clamp(-1.0, 1.0);
+        }
+        let converted: Vec<i16> = self.pending.iter().map(|sample| (sample * i16::MAX as f32) as i16).collect();
+        queue.clear();
+        let _ = queue.queue_audio(&converted);
+        queue.resume();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn channel(waveform: Waveform, notes: Vec<Note>) -> Channel {
+        Channel { waveform, envelope: Envelope::FLAT, notes }
+    }
+
+    #[test]
+    fn length_is_the_notes_played_one_after_another() {
+        let notes = vec![Note::new(440.0, 0.5), Note::new(0.0, 0.25), Note::slide(220.0, 880.0, 0.125)];
+        let expected: usize = notes.iter().map(|note| (note.length * SAMPLE_RATE as f32) as usize).sum();
+        assert_eq!(expected, SAMPLE_RATE as usize * 7 / 8);
+        assert_eq!(render(&[channel(Waveform::Triangle, notes)]).len(), expected);
+    }
+
+    #[test]
+    fn longest_channel_sets_the_length() {
+        let short = channel(Waveform::Square(0.5), vec![Note::new(440.0, 0.25)]);
+        let long = channel(Waveform::Triangle, vec![Note::new(220.0, 0.5)]);
+        assert_eq!(render(&[short, long]).len(), SAMPLE_RATE as usize / 2);
+    }
+
+    #[test]
+    fn samples_stay_in_range() {
+        // Enough loud channels together that the mix has to be clamped
+        let channels: Vec<Channel> = (0..8)
+            .map(|i| Channel { envelope: Envelope::PLUCK, ..channel(if i % 2 == 0 { Waveform::Square(0.5) } else { Waveform::Noise }, vec![Note { volume: 4.0, ..Note::new(110.0 * (i + 1) as f32, 0.2) }]) })
+            .collect();
+        let samples = render(&channels);
+        assert!(!samples.is_empty());
+        assert!(samples.iter().all(|sample| (-1.0..=1.0).contains(sample)));
+    }
+
+    #[test]
+    fn rests_are_silent() {
+        let samples = render(&[channel(Waveform::Square(0.5), vec![Note::new(0.0, 0.1)])]);
+        assert_eq!(samples.len(), SAMPLE_RATE as usize / 10);
+        assert!(samples.iter().all(|sample| *sample == 0.0));
+    }
+
+    #[test]
+    fn noise_is_the_same_every_time() {
+        let noise = || render(&[channel(Waveform::Noise, vec![Note::slide(4_000.0, 500.0, 0.2)])]);
+        let samples = noise();
+        assert!(samples.iter().any(|sample| *sample > 0.0) && samples.iter().any(|sample| *sample < 0.0));
+        assert_eq!(samples, noise());
+    }
+}
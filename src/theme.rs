@@ -0,0 +1,156 @@
+use std::fs;
+use std::path::PathBuf;
+
+use sdl2::image::LoadTexture;
+use sdl2::rect::Rect;
+use sdl2::render::{Canvas, Texture, TextureCreator};
+use sdl2::video::{Window, WindowContext};
+use serde::Deserialize;
+
+use crate::config::Config;
+
+// The art compiled into the game, used when no other theme is picked or one fails to load
+pub const DEFAULT_THEME: &str = "Default";
+pub const DEFAULT_TILE_SIZE: u32 = 32;
+
+const DEBUG_MINE: &[u8; 210] = include_bytes!("../assets/debug_mine.png");
+const FLAGGED_MINE: &[u8; 211] = include_bytes!("../assets/flagged_mine.png");
+const UNFLAGGED_MINE: &[u8; 128] = include_bytes!("../assets/unflagged_mine.png");
+const REVEALED_MINE: &[u8; 128] = include_bytes!("../assets/revealed_mine.png");
+const CURSOR: &[u8; 129] = include_bytes!("../assets/cursor.png");
+
+const REVEALED_MINE_1: &[u8; 253] = include_bytes!("../assets/revealed_mine_1.png");
+const REVEALED_MINE_2: &[u8; 583] = include_bytes!("../assets/revealed_mine_2.png");
+const REVEALED_MINE_3: &[u8; 639] = include_bytes!("../assets/revealed_mine_3.png");
+const REVEALED_MINE_4: &[u8; 410] = include_bytes!("../assets/revealed_mine_4.png");
+const REVEALED_MINE_5: &[u8; 509] = include_bytes!("../assets/revealed_mine_5.png");
+const REVEALED_MINE_6: &[u8; 736] = include_bytes!("../assets/revealed_mine_6.png");
+const REVEALED_MINE_7: &[u8; 427] = include_bytes!("../assets/revealed_mine_7.png");
+const REVEALED_MINE_8: &[u8; 792] = include_bytes!("../assets/revealed_mine_8.png");
+
+const MANIFEST: &str = "theme.toml";
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Tile {
+    Hidden,
+    // Also drawn for hidden cells while they're held down
+    Revealed,
+    Flagged,
+    Exploded,
+    Cursor,
+    // 1 to 8
+    Number(u8),
+}
+
+impl Tile {
+    fn index(self) -> usize {
+        match self {
+            Tile::Hidden => 0,
+            Tile::Revealed => 1,
+            Tile::Flagged => 2,
+            Tile::Exploded => 3,
+            Tile::Cursor => 4,
+            Tile::Number(number) => 4 + number as usize,
+        }
+    }
+}
+
+// theme.toml, tiles are given as the column and row they're at in the atlas
+#[derive(Deserialize)]
+struct Manifest {
+    atlas: String,
+    tile_size: u32,
+    tiles: TileManifest,
+}
+
+#[derive(Deserialize)]
+struct TileManifest {
+    hidden: [u32; 2],
+    revealed: [u32; 2],
+    flagged: [u32; 2],
+    exploded: [u32; 2],
+    cursor: [u32; 2],
+    numbers: [[u32; 2]; 8],
+}
+
+pub struct Theme<'a> {
+    pub name: String,
+    pub tile_size: u32,
+    textures: Vec<Texture<'a>>,
+    // Texture each tile is in, and where in it, indexed by Tile::index
+    tiles: Vec<(usize, Option<Rect>)>,
+}
+
+impl<'a> Theme<'a> {
+    pub fn embedded(texture_creator: &'a TextureCreator<WindowContext>) -> Theme<'a> {
+        let images: [&[u8]; 13] = [UNFLAGGED_MINE, REVEALED_MINE, FLAGGED_MINE, DEBUG_MINE, CURSOR, REVEALED_MINE_1, REVEALED_MINE_2, REVEALED_MINE_3, REVEALED_MINE_4, REVEALED_MINE_5, REVEALED_MINE_6, REVEALED_MINE_7, REVEALED_MINE_8];
+        let textures: Vec<Texture> = images.iter()
+            .map(|image| texture_creator.load_texture_bytes(image).expect("Couldn't create texture from embedded theme"))
+            .collect();
+        Theme {
+            name: DEFAULT_THEME.to_string(),
+            tile_size: DEFAULT_TILE_SIZE,
+            tiles: (0..textures.len()).map(|i| (i, None)).collect(),
+            textures,
+        }
+    }
+
+    // Loads themes/<name>/theme.toml and the atlas it points to
+    pub fn load(texture_creator: &'a TextureCreator<WindowContext>, name: &str) -> Result<Theme<'a>, String> {
+        let directory = themes_directory().ok_or("No config directory")?.join(name);
+        let contents = fs::read_to_string(directory.join(MANIFEST)).map_err(|e| e.to_string())?;
+        let manifest: Manifest = toml::from_str(&contents).map_err(|e| e.to_string())?;
+        if manifest.tile_size == 0 {
+            return Err("tile_size can't be 0".to_string());
+        }
+        let atlas = texture_creator.load_texture(directory.join(&manifest.atlas))?;
+        let tile_rect = |[column, row]: [u32; 2]| Some(Rect::new((column * manifest.tile_size) as i32, (row * manifest.tile_size) as i32, manifest.tile_size, manifest.tile_size));
+        let tiles = manifest.tiles;
+        let mut positions = vec![tiles.hidden, tiles.revealed, tiles.flagged, tiles.exploded, tiles.cursor];
+        positions.extend(tiles.numbers);
+        Ok(Theme {
+            name: name.to_string(),
+            tile_size: manifest.tile_size,
+            textures: vec![atlas],
+            tiles: positions.into_iter().map(|position| (0, tile_rect(position))).collect(),
+        })
+    }
+
+    // Falls back to the embedded theme, the returned theme's name says which one was loaded
+    pub fn load_or_embedded(texture_creator: &'a TextureCreator<WindowContext>, name: &str) -> Theme<'a> {
+        if name == DEFAULT_THEME {
+            return Theme::embedded(texture_creator);
+        }
+        match Theme::load(texture_creator, name) {
+            Ok(theme) => theme,
+            Err(e) => {
+                eprintln!("Couldn't load theme {}: {}", name, e);
+                Theme::embedded(texture_creator)
+            },
+        }
+    }
+
+    pub fn draw(&self, canvas: &mut Canvas<Window>, tile: Tile, rect: Rect) {
+        let (texture, source) = self.tiles[tile.index()];
+        canvas.copy(&self.textures[texture], source, Some(rect)).expect("Couldn't copy canvas");
+    }
+}
+
+pub fn themes_directory() -> Option<PathBuf> {
+    Some(Config::directory()?.join("themes"))
+}
+
+// The embedded theme first, then every directory with a manifest in it
+pub fn available_themes() -> Vec<String> {
+    let mut themes = Vec::new();
+    if let Some(entries) = themes_directory().and_then(|directory| fs::read_dir(directory).ok()) {
+        themes = entries
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| entry.path().join(MANIFEST).is_file())
+            .filter_map(|entry| entry.file_name().into_string().ok())
+            .collect();
+        themes.sort();
+    }
+    themes.insert(0, DEFAULT_THEME.to_string());
+    themes
+}
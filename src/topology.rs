@@ -0,0 +1,277 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+pub enum Shape {
+    #[default]
+    Rectangle,
+    Torus,
+    Hex,
+    // Layers of rectangles stacked on top of each other
+    Layers,
+}
+
+pub const SHAPES: [Shape; 4] = [Shape::Rectangle, Shape::Torus, Shape::Hex, Shape::Layers];
+
+pub const DEFAULT_LAYERS: usize = 3;
+
+// Which cells a number counts, as steps from the cell
+#[derive(Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+pub enum Neighbourhood {
+    // The eight cells around
+    #[default]
+    Standard,
+    // Where a chess knight could move to
+    Knight,
+    // Only straight up, down, left and right
+    Cross,
+    // The sixteen cells two away, skipping the ones touching
+    Ring,
+}
+
+pub const NEIGHBOURHOODS: [Neighbourhood; 4] = [Neighbourhood::Standard, Neighbourhood::Knight, Neighbourhood::Cross, Neighbourhood::Ring];
+
+const STANDARD_OFFSETS: [(i32, i32); 8] = [(-1, -1), (0, -1), (1, -1), (-1, 0), (1, 0), (-1, 1), (0, 1), (1, 1)];
+const KNIGHT_OFFSETS: [(i32, i32); 8] = [(-1, -2), (1, -2), (-2, -1), (2, -1), (-2, 1), (2, 1), (-1, 2), (1, 2)];
+const CROSS_OFFSETS: [(i32, i32); 4] = [(0, -1), (-1, 0), (1, 0), (0, 1)];
+const RING_OFFSETS: [(i32, i32); 16] = [(-2, -2), (-1, -2), (0, -2), (1, -2), (2, -2), (-2, -1), (2, -1), (-2, 0), (2, 0), (-2, 1), (2, 1), (-2, 2), (-1, 2), (0, 2), (1, 2), (2, 2)];
+
+impl Neighbourhood {
+    pub fn name(self) -> &'static str {
+        match self {
+            Neighbourhood::Standard => "Standard",
+            Neighbourhood::Knight => "Knight",
+            Neighbourhood::Cross => "Cross",
+            Neighbourhood::Ring => "Ring",
+        }
+    }
+
+    pub fn offsets(self) -> &'static [(i32, i32)] {
+        match self {
+            Neighbourhood::Standard => &STANDARD_OFFSETS,
+            Neighbourhood::Knight => &KNIGHT_OFFSETS,
+            Neighbourhood::Cross => &CROSS_OFFSETS,
+            Neighbourhood::Ring => &RING_OFFSETS,
+        }
+    }
+
+    // The cells around x, y with nothing in the way, boards with edges keep the ones on the board and endless boards use them all
+    pub fn around(self, x: i32, y: i32) -> impl Iterator<Item = (i32, i32)> {
+        self.offsets().iter().map(move |(step_x, step_y)| (x + step_x, y + step_y))
+    }
+}
+
+impl Shape {
+    pub fn name(self) -> &'static str {
+        match self {
+            Shape::Rectangle => "Rectangle",
+            Shape::Torus => "Torus",
+            Shape::Hex => "Hex",
+            Shape::Layers => "3D",
+        }
+    }
+}
+
+// How the cells of a board are connected, cells are always numbered from 0
+pub trait Topology {
+    // Adds the cells touching this one to neighbours, which is left as it was otherwise so it can be reused
+    fn neighbours(&self, index: usize, neighbours: &mut Vec<usize>);
+}
+
+// The standard board, nothing past the edges
+pub struct Rectangle {
+    pub width: usize,
+    pub height: usize,
+    pub neighbourhood: Neighbourhood,
+}
+
+impl Topology for Rectangle {
+    fn neighbours(&self, index: usize, neighbours: &mut Vec<usize>) {
+        let (x, y) = ((index % self.width) as i32, (index / self.width) as i32);
+        for (neighbour_x, neighbour_y) in self.neighbourhood.around(x, y) {
+            if (0..self.width as i32).contains(&neighbour_x) && (0..self.height as i32).contains(&neighbour_y) {
+                neighbours.push(neighbour_y as usize * self.width + neighbour_x as usize);
+            }
+        }
+    }
+}
+
+// Left and right edges are neighbours, and so are the top and bottom
+pub struct Torus {
+    pub width: usize,
+    pub height: usize,
+    pub neighbourhood: Neighbourhood,
+}
+
+impl Topology for Torus {
+    fn neighbours(&self, index: usize, neighbours: &mut Vec<usize>) {
+        let (x, y) = ((index % self.width) as i32, (index / self.width) as i32);
+        let start = neighbours.len();
+        for (neighbour_x, neighbour_y) in self.neighbourhood.around(x, y) {
+            let neighbour = neighbour_y.rem_euclid(self.height as i32) as usize * self.width + neighbour_x.rem_euclid(self.width as i32) as usize;
+            // Small boards wrap onto the same cell more than once
+            if neighbour != index && !neighbours[start..].contains(&neighbour) {
+                neighbours.push(neighbour);
+            }
+        }
+    }
+}
+
+// Rows of hexagons with every odd row pushed half a cell right, so each cell has six neighbours.
+// Hexes always use their own six, whatever the neighbourhood.
+pub struct Hex {
+    pub width: usize,
+    pub height: usize,
+}
+
+impl Topology for Hex {
+    fn neighbours(&self, index: usize, neighbours: &mut Vec<usize>) {
+        let (x, y) = ((index % self.width) as i32, (index / self.width) as i32);
+        // The rows above and below reach one further left on even rows and one further right on odd rows
+        let shift = y % 2;
+        for (neighbour_x, neighbour_y) in [(x - 1, y), (x + 1, y), (x - 1 + shift, y - 1), (x + shift, y - 1), (x - 1 + shift, y + 1), (x + shift, y + 1)] {
+            if (0..self.width as i32).contains(&neighbour_x) && (0..self.height as i32).contains(&neighbour_y) {
+                neighbours.push(neighbour_y as usize * self.width + neighbour_x as usize);
+            }
+        }
+    }
+}
+
+// The neighbourhood on the cell's own layer, and the cells right above and below along with their neighbourhoods.
+// That's up to 26 neighbours with the standard neighbourhood.
+pub struct Layered {
+    pub width: usize,
+    pub height: usize,
+    pub layers: usize,
+    pub neighbourhood: Neighbourhood,
+}
+
+impl Topology for Layered {
+    fn neighbours(&self, index: usize, neighbours: &mut Vec<usize>) {
+        let layer_size = self.width * self.height;
+        let (layer, index_in_layer) = (index / layer_size, index % layer_size);
+        let flat = Rectangle { width: self.width, height: self.height, neighbourhood: self.neighbourhood };
+        let start = neighbours.len();
+        flat.neighbours(index_in_layer, neighbours);
+        let around = neighbours.len() - start;
+        for neighbour_layer in layer.saturating_sub(1)..=(layer + 1).min(self.layers - 1) {
+            let layer_start = neighbour_layer * layer_size;
+            if neighbour_layer == layer {
+                for neighbour in &mut neighbours[start..start + around] {
+                    *neighbour += layer_start;
+                }
+            } else {
+                neighbours.push(layer_start + index_in_layer);
+                for i in start..start + around {
+                    neighbours.push(layer_start + neighbours[i] % layer_size);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn neighbours(topology: &dyn Topology, index: usize) -> Vec<usize> {
+        let mut neighbours = Vec::new();
+        topology.neighbours(index, &mut neighbours);
+        neighbours.sort_unstable();
+        neighbours
+    }
+
+    fn all_topologies(width: usize, height: usize) -> Vec<(Box<dyn Topology>, usize)> {
+        let mut topologies: Vec<(Box<dyn Topology>, usize)> = vec![(Box::new(Hex { width, height }), width * height)];
+        for neighbourhood in NEIGHBOURHOODS {
+            topologies.push((Box::new(Rectangle { width, height, neighbourhood }), width * height));
+            topologies.push((Box::new(Torus { width, height, neighbourhood }), width * height));
+            topologies.push((Box::new(Layered { width, height, layers: 3, neighbourhood }), width * height * 3));
+        }
+        topologies
+    }
+
+    // Corner, middle of the top edge and middle of a 7 by 7 board
+    #[test]
+    fn rectangle_counts() {
+        for (neighbourhood, counts) in [(Neighbourhood::Standard, [3, 5, 8]), (Neighbourhood::Knight, [2, 4, 8]), (Neighbourhood::Cross, [2, 3, 4]), (Neighbourhood::Ring, [5, 9, 16])] {
+            let rectangle = Rectangle { width: 7, height: 7, neighbourhood };
+            for (index, count) in [0, 3, 24].into_iter().zip(counts) {
+                assert_eq!(neighbours(&rectangle, index).len(), count);
+            }
+            // The other corners too
+            for index in [6, 42, 48] {
+                assert_eq!(neighbours(&rectangle, index).len(), counts[0]);
+            }
+        }
+    }
+
+    #[test]
+    fn torus_counts_are_the_same_everywhere() {
+        for neighbourhood in NEIGHBOURHOODS {
+            let torus = Torus { width: 7, height: 7, neighbourhood };
+            for index in 0..49 {
+                assert_eq!(neighbours(&torus, index).len(), neighbourhood.offsets().len());
+            }
+        }
+    }
+
+    #[test]
+    fn narrow_torus_lists_each_cell_once() {
+        for (width, height) in [(1, 1), (1, 2), (1, 5), (2, 1), (2, 2), (2, 5), (5, 1), (5, 2)] {
+            for neighbourhood in NEIGHBOURHOODS {
+                let torus = Torus { width, height, neighbourhood };
+                for index in 0..width * height {
+                    let neighbours = neighbours(&torus, index);
+                    assert!(neighbours.windows(2).all(|pair| pair[0] != pair[1]));
+                    assert!(!neighbours.contains(&index));
+                    assert!(neighbours.iter().all(|neighbour| *neighbour < width * height));
+                }
+            }
+        }
+        // Every other cell of a 1 by 5 torus with the standard neighbourhood is the one above or below
+        assert_eq!(neighbours(&Torus { width: 1, height: 5, neighbourhood: Neighbourhood::Standard }, 0), vec![1, 4]);
+    }
+
+    #[test]
+    fn hex_rows_lean_by_parity() {
+        let hex = Hex { width: 5, height: 5 };
+        // Even row, x 2 y 2, reaches left into the rows above and below
+        assert_eq!(neighbours(&hex, 12), vec![6, 7, 11, 13, 16, 17]);
+        // Odd row, x 2 y 1, reaches right
+        assert_eq!(neighbours(&hex, 7), vec![2, 3, 6, 8, 12, 13]);
+        // Corners of an even row and an odd row
+        assert_eq!(neighbours(&hex, 0), vec![1, 5]);
+        assert_eq!(neighbours(&hex, 9), vec![4, 8, 14]);
+    }
+
+    #[test]
+    fn layered_counts() {
+        let layered = Layered { width: 5, height: 5, layers: 3, neighbourhood: Neighbourhood::Standard };
+        // The middle of the middle layer
+        assert_eq!(neighbours(&layered, 25 + 12).len(), 26);
+        // The middle of the top and bottom layers only have one layer next to them
+        assert_eq!(neighbours(&layered, 12).len(), 17);
+        assert_eq!(neighbours(&layered, 50 + 12).len(), 17);
+        for index in 0..75 {
+            let neighbours = neighbours(&layered, index);
+            assert!(neighbours.iter().all(|neighbour| *neighbour < 75 && (neighbour / 25).abs_diff(index / 25) <= 1));
+            assert!(neighbours.windows(2).all(|pair| pair[0] != pair[1]));
+        }
+        let flat = Layered { width: 5, height: 5, layers: 1, neighbourhood: Neighbourhood::Standard };
+        assert_eq!(neighbours(&flat, 12).len(), 8);
+    }
+
+    // Numbers and chords rely on a cell being a neighbour of its neighbours
+    #[test]
+    fn neighbours_are_mutual() {
+        for (width, height) in [(7, 6), (2, 5), (1, 3)] {
+            for (topology, cells) in all_topologies(width, height) {
+                for index in 0..cells {
+                    for neighbour in neighbours(topology.as_ref(), index) {
+                        assert!(neighbours(topology.as_ref(), neighbour).contains(&index));
+                    }
+                }
+            }
+        }
+    }
+}